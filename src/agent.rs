@@ -0,0 +1,282 @@
+//!
+//! Agents choosing actions, to be compared with each other in an `Arena`.
+//!
+
+use rand::{Rng, XorShiftRng};
+
+use error::GameError;
+use mcts::{Game, GameAction, MCTS, SearchSettings, expected_reward, playout};
+use utils::seeded_rng;
+
+/// Something that plays a game: chooses an action given a game state.
+pub trait Agent<G: Game<A>, A: GameAction> {
+
+    /// Short description for reports.
+    fn name(&self) -> String;
+
+    /// Choose an action for the player to move, or `None` if there is no
+    /// allowed action.
+    fn choose_action(&mut self, game: &G) -> Result<Option<A>, GameError>;
+
+    /// Prepare for a new game; all random decisions should derive from `seed`.
+    fn reset(&mut self, _seed: u32) {}
+}
+
+/// Choose uniformly among the allowed actions.
+#[derive(Debug, Clone)]
+pub struct RandomAgent {
+    rng: XorShiftRng,
+}
+
+impl RandomAgent {
+    pub fn new() -> RandomAgent {
+        RandomAgent { rng: seeded_rng(0, 0) }
+    }
+}
+
+impl Default for RandomAgent {
+    fn default() -> RandomAgent {
+        RandomAgent::new()
+    }
+}
+
+impl<G: Game<A>, A: GameAction> Agent<G, A> for RandomAgent {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn choose_action(&mut self, game: &G) -> Result<Option<A>, GameError> {
+        Ok(game.random_action(&mut self.rng))
+    }
+
+    fn reset(&mut self, seed: u32) {
+        self.rng = seeded_rng(seed, 0);
+    }
+}
+
+/// Choose the action with the highest immediate reward for the player to
+/// move; ties are broken at random.
+#[derive(Debug, Clone)]
+pub struct GreedyAgent {
+    rng: XorShiftRng,
+}
+
+impl GreedyAgent {
+    pub fn new() -> GreedyAgent {
+        GreedyAgent { rng: seeded_rng(0, 0) }
+    }
+}
+
+impl Default for GreedyAgent {
+    fn default() -> GreedyAgent {
+        GreedyAgent::new()
+    }
+}
+
+impl<G: Game<A>, A: GameAction> Agent<G, A> for GreedyAgent {
+    fn name(&self) -> String {
+        "greedy".to_string()
+    }
+
+    fn choose_action(&mut self, game: &G) -> Result<Option<A>, GameError> {
+        let player = game.current_player();
+        let mut actions = game.allowed_actions();
+        self.rng.shuffle(&mut actions);
+        let scores = actions.iter()
+                .map(|action| {
+                    let mut next = game.clone();
+                    next.try_make_move(action)?;
+                    Ok(next.reward_for(player))
+                })
+                .collect::<Result<Vec<_>, GameError>>()?;
+        Ok(best_scored(&actions, &scores))
+    }
+
+    fn reset(&mut self, seed: u32) {
+        self.rng = seeded_rng(seed, 0);
+    }
+}
+
+/// Flat Monte Carlo: estimate every allowed action with `playouts` random
+/// playouts and choose the best one.
+#[derive(Debug, Clone)]
+pub struct FlatMonteCarloAgent {
+    playouts: usize,
+    max_depth: Option<usize>,
+    rng: XorShiftRng,
+}
+
+impl FlatMonteCarloAgent {
+    pub fn new(playouts: usize, max_depth: Option<usize>) -> FlatMonteCarloAgent {
+        FlatMonteCarloAgent {
+            playouts,
+            max_depth,
+            rng: seeded_rng(0, 0),
+        }
+    }
+
+    /// Mean reward of random playouts from `game` for `player`.
+    fn estimate<G: Game<A>, A: GameAction>(&mut self, game: &G, player: usize) -> Result<f32, GameError> {
+        if game.num_players() == 1 {
+            return expected_reward(game, self.playouts, self.max_depth, &mut self.rng);
+        }
+        let mut sum = 0.;
+        for _ in 0..self.playouts {
+            sum += playout(game, self.max_depth, &mut self.rng)?.reward_for(player);
+        }
+        Ok(sum / self.playouts as f32)
+    }
+}
+
+impl<G: Game<A>, A: GameAction> Agent<G, A> for FlatMonteCarloAgent {
+    fn name(&self) -> String {
+        format!("flat-mc({})", self.playouts)
+    }
+
+    fn choose_action(&mut self, game: &G) -> Result<Option<A>, GameError> {
+        let player = game.current_player();
+        let actions = game.allowed_actions();
+        let mut scores = Vec::with_capacity(actions.len());
+        for action in &actions {
+            let mut next = game.clone();
+            next.try_make_move(action)?;
+            scores.push(self.estimate(&next, player)?);
+        }
+        Ok(best_scored(&actions, &scores))
+    }
+
+    fn reset(&mut self, seed: u32) {
+        self.rng = seeded_rng(seed, 0);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// How long an `MctsAgent` searches per move.
+pub enum Budget {
+    /// A fixed number of iterations per tree; reproducible.
+    Iterations(usize),
+    /// A time budget in seconds.
+    Seconds(f32),
+}
+
+/// Choose actions with an ensemble of MCTS trees searched from scratch
+/// for every move.
+#[derive(Debug, Clone)]
+pub struct MctsAgent {
+    ensemble_size: usize,
+    budget: Budget,
+    c: f32,
+    settings: SearchSettings,
+    seed: u32,
+    moves: u32,
+}
+
+impl MctsAgent {
+    pub fn new(ensemble_size: usize, budget: Budget, c: f32) -> MctsAgent {
+        MctsAgent {
+            ensemble_size,
+            budget,
+            c,
+            settings: SearchSettings::default(),
+            seed: 0,
+            moves: 0,
+        }
+    }
+
+    /// Change the settings used by the search.
+    pub fn set_settings(&mut self, settings: SearchSettings) {
+        self.settings = settings;
+    }
+}
+
+impl<G: Game<A>, A: GameAction> Agent<G, A> for MctsAgent {
+    fn name(&self) -> String {
+        match self.budget {
+            Budget::Iterations(n) => format!("mcts({}x{})", self.ensemble_size, n),
+            Budget::Seconds(s) => format!("mcts({}x{}s)", self.ensemble_size, s),
+        }
+    }
+
+    fn choose_action(&mut self, game: &G) -> Result<Option<A>, GameError> {
+        let mut mcts = MCTS::with_seed(game, self.ensemble_size, self.seed.wrapping_add(self.moves));
+        mcts.set_settings(self.settings);
+        self.moves += 1;
+        match self.budget {
+            Budget::Iterations(n) => mcts.search(n, self.c)?,
+            Budget::Seconds(s) => mcts.search_time(s, self.c)?,
+        }
+        Ok(mcts.best_action())
+    }
+
+    fn reset(&mut self, seed: u32) {
+        self.seed = seed;
+        self.moves = 0;
+    }
+}
+
+/// The action with the highest score; the first one on ties.
+fn best_scored<A: GameAction>(actions: &[A], scores: &[f32]) -> Option<A> {
+    let mut best: Option<(A, f32)> = None;
+    for (action, &score) in actions.iter().zip(scores) {
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((*action, score));
+        }
+    }
+    best.map(|(action, _)| action)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use agent::*;
+    use mcts::Game;
+    use minigame::MiniGame;
+    use twofortyeight::TwoFortyEight;
+
+    /// Play a single-player game to the end and return the final reward.
+    fn play<G: Game<A>, A: GameAction>(agent: &mut dyn Agent<G, A>, game: &G) -> f32 {
+        let mut game = game.clone();
+        while let Some(action) = agent.choose_action(&game).unwrap() {
+            game.make_move(&action);
+        }
+        game.reward()
+    }
+
+    #[test]
+    fn test_agents() {
+        let game = MiniGame::new();
+        let mut agents: Vec<Box<dyn Agent<MiniGame, _>>> = vec![
+            Box::new(RandomAgent::new()),
+            Box::new(GreedyAgent::new()),
+            Box::new(FlatMonteCarloAgent::new(20, None)),
+            Box::new(MctsAgent::new(2, Budget::Iterations(100), 1.)),
+        ];
+        for agent in &mut agents {
+            agent.reset(1);
+            let reward = play(agent.as_mut(), &game);
+            assert!(reward == 1. || reward == -1., "{}: {}", agent.name(), reward);
+        }
+
+        // Searching agents find the exact sum
+        assert_eq!(play(agents[3].as_mut(), &game), 1.);
+    }
+
+    #[test]
+    fn test_greedy_twofortyeight() {
+        let game = TwoFortyEight::new();
+        let mut agent = GreedyAgent::new();
+        let action = agent.choose_action(&game).unwrap().unwrap();
+
+        let best = game.allowed_actions().iter()
+                .map(|a| {
+                    let mut next = game.clone();
+                    next.make_move(a);
+                    next.reward()
+                })
+                .fold(f32::MIN, f32::max);
+        let mut next = game.clone();
+        next.make_move(&action);
+        assert_eq!(next.reward(), best);
+    }
+}
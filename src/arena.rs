@@ -0,0 +1,381 @@
+//!
+//! Play many games between agents and collect the results.
+//!
+//! In games with several players the agents take turns in every seat: game
+//! `i` is played with seed `first_seed + i / n` and the seats rotated by
+//! `i % n` for `n` agents, so every agent plays every seed from every seat.
+//! Single-player games simply collect the score distribution of one agent.
+//!
+//! For two-player games `round_robin` rates a pool of agents and `sprt`
+//! plays a candidate against a baseline until the sequential probability
+//! ratio test accepts one of its hypotheses (see the `elo` module).
+//!
+
+use std::marker::PhantomData;
+
+use agent::Agent;
+use elo::{Rating, Score, Sprt, SprtStatus, fit_ratings};
+use error::GameError;
+use mcts::{Game, GameAction};
+
+#[derive(Debug, Clone)]
+/// The moves and final rewards of a single game.
+pub struct GameRecord<A: GameAction> {
+    pub seed: u32,
+    pub actions: Vec<A>,
+    /// Final reward of every seat.
+    pub rewards: Vec<f32>,
+}
+
+impl<A: GameAction> GameRecord<A> {
+    /// Seats with the highest reward.
+    pub fn winners(&self) -> Vec<usize> {
+        let best = self.rewards.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        (0..self.rewards.len())
+                .filter(|&seat| self.rewards[seat] == best)
+                .collect()
+    }
+
+    /// Points scored by `seat`: 1 for a win, 1/2 for a shared win, 0 otherwise.
+    pub fn points(&self, seat: usize) -> f32 {
+        let winners = self.winners();
+        if !winners.contains(&seat) {
+            0.
+        } else if winners.len() == 1 {
+            1.
+        } else {
+            0.5
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Result of a match stopped by a sequential probability ratio test.
+pub struct SprtOutcome {
+    pub status: SprtStatus,
+    /// Results of the candidate against the baseline.
+    pub score: Score,
+    /// Final log-likelihood ratio.
+    pub llr: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Results of one agent over all games played in the arena.
+pub struct AgentResult {
+    pub name: String,
+    /// Games won outright, shared with other agents, and lost.
+    ///
+    /// Only counted for games with more than one player.
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    /// Final reward of every game.
+    pub rewards: Vec<f32>,
+}
+
+impl AgentResult {
+    /// Number of games played.
+    pub fn games(&self) -> usize {
+        self.rewards.len()
+    }
+
+    /// Share of points, counting draws as half a win.
+    pub fn win_rate(&self) -> f32 {
+        (self.wins as f32 + 0.5 * self.draws as f32) / self.games() as f32
+    }
+
+    /// Mean final reward.
+    pub fn mean_reward(&self) -> f32 {
+        self.rewards.iter().sum::<f32>() / self.games() as f32
+    }
+
+    /// Score distribution: (lowest, median, highest) final reward.
+    pub fn reward_range(&self) -> Option<(f32, f32, f32)> {
+        let mut sorted = self.rewards.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).expect("NaN reward"));
+        if sorted.is_empty() {
+            None
+        } else {
+            Some((sorted[0], sorted[sorted.len() / 2], sorted[sorted.len() - 1]))
+        }
+    }
+}
+
+/// Plays agents against each other starting from a given game state.
+#[derive(Debug, Clone)]
+pub struct Arena<G: Game<A>, A: GameAction> {
+    game: G,
+    max_moves: Option<usize>,
+    first_seed: u32,
+    _action: PhantomData<A>,
+}
+
+impl<G: Game<A>, A: GameAction> Arena<G, A> {
+
+    pub fn new(game: &G) -> Arena<G, A> {
+        Arena {
+            game: game.clone(),
+            max_moves: None,
+            first_seed: 0,
+            _action: PhantomData,
+        }
+    }
+
+    /// Stop every game after `max_moves` moves (`None`: play until the end).
+    pub fn set_max_moves(&mut self, max_moves: Option<usize>) {
+        self.max_moves = max_moves;
+    }
+
+    /// Use the game seeds starting at `seed`.
+    pub fn set_first_seed(&mut self, seed: u32) {
+        self.first_seed = seed;
+    }
+
+    /// Play a single game; `seats[p]` plays for player `p`.
+    pub fn play(&self, seats: &mut [&mut dyn Agent<G, A>], seed: u32) -> Result<GameRecord<A>, GameError> {
+        let mut game = self.game.clone();
+        game.set_rng_seed(seed);
+        let players = game.num_players();
+        if seats.len() != players {
+            return Err(GameError::Other(format!("{} agents for a game of {} players", seats.len(), players)));
+        }
+        for agent in seats.iter_mut() {
+            agent.reset(seed);
+        }
+
+        let mut actions = Vec::new();
+        while game.decided_reward().is_none() && self.max_moves.is_none_or(|max_moves| actions.len() < max_moves) {
+            let action = match seats[game.current_player()].choose_action(&game)? {
+                Some(action) => action,
+                None => break,
+            };
+            game.try_make_move(&action)?;
+            actions.push(action);
+        }
+
+        Ok(GameRecord {
+            seed,
+            actions,
+            rewards: (0..players).map(|player| game.reward_for(player)).collect(),
+        })
+    }
+
+    /// Play `games` games, rotating the agents through the seats.
+    ///
+    /// Returns the results per agent, in the order of `agents`.
+    pub fn run(&self, agents: &mut [&mut dyn Agent<G, A>], games: usize) -> Result<Vec<AgentResult>, GameError> {
+        let n = agents.len();
+        let mut results = agents.iter()
+                .map(|agent| AgentResult { name: agent.name(), .. AgentResult::default() })
+                .collect::<Vec<_>>();
+
+        for i in 0..games {
+            let seed = self.first_seed + (i / n) as u32;
+            let rotation = i % n;
+
+            // Seat s is taken by agent (s + rotation) % n
+            agents.rotate_left(rotation);
+            let record = self.play(agents, seed);
+            agents.rotate_right(rotation);
+            let record = record?;
+
+            for seat in 0..n {
+                let result = &mut results[(seat + rotation) % n];
+                result.rewards.push(record.rewards[seat]);
+                if n > 1 {
+                    match record.points(seat) {
+                        0. => result.losses += 1,
+                        0.5 => result.draws += 1,
+                        _ => result.wins += 1,
+                    }
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Let every pair of agents play `games` games of a two-player game
+    /// and fit Elo ratings to the results.
+    pub fn round_robin(&self, agents: &mut [&mut dyn Agent<G, A>], games: usize) -> Result<Vec<Rating>, GameError> {
+        let n = agents.len();
+        let names = agents.iter().map(|agent| agent.name()).collect::<Vec<_>>();
+        let mut pairs = Vec::new();
+        for j in 1..n {
+            let (left, right) = agents.split_at_mut(j);
+            for (i, agent) in left.iter_mut().enumerate() {
+                let results = self.run(&mut [&mut **agent, &mut *right[0]], games)?;
+                pairs.push((i, j, Score::from(&results[0])));
+            }
+        }
+
+        let mut scores = vec![vec![Score::default(); n]; n];
+        for (i, j, score) in pairs {
+            scores[i][j] = score;
+            scores[j][i] = score.reversed();
+        }
+        Ok(fit_ratings(&names, &scores))
+    }
+
+    /// Play `candidate` against `baseline` in a two-player game until `sprt`
+    /// reaches a decision or `max_games` games have been played.
+    ///
+    /// Games are played in pairs with the same seed, once from every seat.
+    pub fn sprt(&self, candidate: &mut dyn Agent<G, A>, baseline: &mut dyn Agent<G, A>, sprt: &Sprt, max_games: usize) -> Result<SprtOutcome, GameError> {
+        let mut score = Score::default();
+        let mut seed = self.first_seed;
+        let mut status = SprtStatus::Continue;
+
+        while status == SprtStatus::Continue && score.games() < max_games {
+            let first = self.play(&mut [&mut *candidate, &mut *baseline], seed)?;
+            score.add(first.points(0));
+            let second = self.play(&mut [&mut *baseline, &mut *candidate], seed)?;
+            score.add(second.points(1));
+
+            status = sprt.status(&score);
+            seed += 1;
+        }
+        Ok(SprtOutcome {
+            status,
+            score,
+            llr: sprt.llr(&score),
+        })
+    }
+}
+
+impl<'a> From<&'a AgentResult> for Score {
+    fn from(result: &'a AgentResult) -> Score {
+        Score {
+            wins: result.wins,
+            draws: result.draws,
+            losses: result.losses,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use agent::*;
+    use arena::*;
+    use elo::{Sprt, SprtStatus};
+    use minigame::MiniGame;
+    use twofortyeight::TwoFortyEight;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Take(u32);
+    impl GameAction for Take {}
+
+    /// Two players alternately take 1 or 2 stones; whoever takes the last one wins.
+    #[derive(Debug, Clone)]
+    struct Stones {
+        left: u32,
+        player: usize,
+    }
+
+    impl Game<Take> for Stones {
+        fn allowed_actions(&self) -> Vec<Take> {
+            (1..3).filter(|&n| n <= self.left).map(Take).collect()
+        }
+
+        fn make_move(&mut self, action: &Take) {
+            self.left -= action.0;
+            self.player = 1 - self.player;
+        }
+
+        fn reward(&self) -> f32 {
+            self.reward_for(0)
+        }
+
+        fn set_rng_seed(&mut self, _: u32) {}
+
+        fn num_players(&self) -> usize {
+            2
+        }
+
+        fn current_player(&self) -> usize {
+            self.player
+        }
+
+        fn reward_for(&self, player: usize) -> f32 {
+            match self.left {
+                0 if player == self.player => -1.,
+                0 => 1.,
+                _ => 0.,
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_player() {
+        let arena = Arena::new(&TwoFortyEight::new());
+        let mut random = RandomAgent::new();
+
+        let results = arena.run(&mut [&mut random], 3).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].games(), 3);
+        assert_eq!(results[0].wins + results[0].draws + results[0].losses, 0);
+        let (min, median, max) = results[0].reward_range().unwrap();
+        assert!(min > 0. && min <= median && median <= max);
+
+        // Every game uses its own seed
+        let mut random = RandomAgent::new();
+        let record = arena.play(&mut [&mut random], 1).unwrap();
+        assert_eq!(record.rewards[0], results[0].rewards[1]);
+    }
+
+    #[test]
+    fn test_two_players() {
+        let arena = Arena::new(&Stones { left: 7, player: 0 });
+        let mut mcts = MctsAgent::new(1, Budget::Iterations(200), 1.);
+        let mut random = RandomAgent::new();
+
+        let results = arena.run(&mut [&mut mcts, &mut random], 10).unwrap();
+        for result in &results {
+            assert_eq!(result.games(), 10);
+            assert_eq!(result.wins + result.draws + result.losses, 10);
+        }
+        assert_eq!(results[0].wins, results[1].losses);
+        assert_eq!(results[0].win_rate() + results[1].win_rate(), 1.);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let arena = Arena::new(&Stones { left: 7, player: 0 });
+        let mut strong = FlatMonteCarloAgent::new(50, None);
+        let mut weak = FlatMonteCarloAgent::new(1, None);
+        let mut random = RandomAgent::new();
+
+        let ratings = arena.round_robin(&mut [&mut strong, &mut weak, &mut random], 10).unwrap();
+        assert_eq!(ratings.len(), 3);
+        assert!(ratings.iter().all(|rating| rating.games == 20));
+        assert!(ratings[0].elo > ratings[2].elo);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let arena = Arena::new(&Stones { left: 7, player: 0 });
+        let mut flat_mc = FlatMonteCarloAgent::new(50, None);
+        let mut random = RandomAgent::new();
+        let sprt = Sprt::new(0., 50., 0.05, 0.05);
+
+        let outcome = arena.sprt(&mut flat_mc, &mut random, &sprt, 1000).unwrap();
+        assert_eq!(outcome.status, SprtStatus::AcceptH1);
+        assert!(outcome.score.games() < 1000);
+        assert!(outcome.llr >= sprt.bounds().1);
+
+        // Stop after max_games without a decision
+        let mut a = RandomAgent::new();
+        let mut b = RandomAgent::new();
+        let outcome = arena.sprt(&mut a, &mut b, &sprt, 4).unwrap();
+        assert_eq!(outcome.score.games(), 4);
+    }
+
+    #[test]
+    fn test_wrong_number_of_agents() {
+        let arena = Arena::new(&MiniGame::new());
+        let mut a = RandomAgent::new();
+        let mut b = RandomAgent::new();
+        assert!(arena.play(&mut [&mut a, &mut b], 0).is_err());
+    }
+}
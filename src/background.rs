@@ -0,0 +1,208 @@
+//!
+//! Run an MCTS search on a background thread.
+//!
+//! A `BackgroundSearch` takes ownership of an `MCTS` solver and keeps
+//! searching until it is stopped; the best action found so far can be
+//! queried at any time. Pondering starts such a search on the position
+//! after the expected opponent reply while the opponent is still thinking.
+//!
+
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use error::GameError;
+use mcts::{Game, GameAction, MCTS};
+
+#[derive(Debug, Clone)]
+/// State published by the searching thread.
+struct Progress<A> {
+    best_action: Option<A>,
+    iterations: usize,
+    error: Option<GameError>,
+}
+
+/// Handle to an MCTS search running on a background thread.
+pub struct BackgroundSearch<G: Game<A>, A: GameAction> {
+    stop: Arc<AtomicBool>,
+    progress: Arc<Mutex<Progress<A>>>,
+    handle: thread::JoinHandle<Result<MCTS<G, A>, GameError>>,
+    c: f32,
+    pondering: Option<A>,
+}
+
+impl<G, A> BackgroundSearch<G, A>
+    where G: Game<A> + Send + 'static, A: GameAction + Send + 'static
+{
+    /// Start searching with exploration constant `c` until `stop` is called.
+    pub fn start(mcts: MCTS<G, A>, c: f32) -> BackgroundSearch<G, A> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let progress = Arc::new(Mutex::new(Progress {
+            best_action: mcts.best_action(),
+            iterations: mcts.iterations(),
+            error: None,
+        }));
+
+        let handle = {
+            let stop = stop.clone();
+            let progress = progress.clone();
+            let mut mcts = mcts;
+            thread::spawn(move || {
+                let result = mcts.search_until(c, &stop, |mcts| {
+                    let mut progress = progress.lock().unwrap();
+                    progress.best_action = mcts.best_action();
+                    progress.iterations = mcts.iterations();
+                });
+                if let Err(ref err) = result {
+                    progress.lock().unwrap().error = Some(err.clone());
+                }
+                result.map(|_| mcts)
+            })
+        };
+
+        BackgroundSearch {
+            stop,
+            progress,
+            handle,
+            c,
+            pondering: None,
+        }
+    }
+
+    /// Ponder: search the position after the `expected_reply` of the opponent to `game`.
+    pub fn ponder(mut mcts: MCTS<G, A>, game: &G, expected_reply: &A, c: f32) -> Result<BackgroundSearch<G, A>, GameError> {
+        let mut game = game.clone();
+        game.try_make_move(expected_reply)?;
+        mcts.advance_game(&game);
+
+        let mut search = BackgroundSearch::start(mcts, c);
+        search.pondering = Some(*expected_reply);
+        Ok(search)
+    }
+
+    /// The opponent reply this search is pondering on, if any.
+    pub fn pondering(&self) -> Option<A> {
+        self.pondering
+    }
+
+    /// The opponent played `reply`, which led to `game`.
+    ///
+    /// If we were pondering on exactly this reply the search simply continues;
+    /// otherwise it is restarted on the new position.
+    pub fn opponent_moved(mut self, reply: &A, game: &G) -> Result<BackgroundSearch<G, A>, GameError> {
+        if self.pondering == Some(*reply) {
+            self.pondering = None;
+            return Ok(self);
+        }
+
+        let c = self.c;
+        let mut mcts = self.stop()?;
+        mcts.advance_game(game);
+        Ok(BackgroundSearch::start(mcts, c))
+    }
+
+    /// The best action found so far.
+    pub fn best_action(&self) -> Option<A> {
+        self.progress.lock().unwrap().best_action
+    }
+
+    /// Number of MCTS iterations per tree performed so far.
+    pub fn iterations(&self) -> usize {
+        self.progress.lock().unwrap().iterations
+    }
+
+    /// The error that ended the search, if any.
+    ///
+    /// After an error the search thread has finished; `best_action` and
+    /// `iterations` keep their last values and `stop` returns the error.
+    pub fn error(&self) -> Option<GameError> {
+        self.progress.lock().unwrap().error.clone()
+    }
+
+    /// Has the search ended with an error?
+    pub fn is_failed(&self) -> bool {
+        self.progress.lock().unwrap().error.is_some()
+    }
+
+    /// Stop the search and hand back the solver.
+    ///
+    /// Fails with the first error reported by the game; the solver is lost
+    /// in that case.
+    pub fn stop(self) -> Result<MCTS<G, A>, GameError> {
+        self.stop.store(true, Ordering::SeqCst);
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(err) => panic::resume_unwind(err),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use background::*;
+    use error::GameError;
+    use minigame::{MiniGame, Tweaked};
+
+    #[test]
+    fn test_start_stop() {
+        let game = MiniGame::new();
+        let search = BackgroundSearch::start(MCTS::new(&game, 2), 1.);
+
+        while search.iterations() < 100 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(search.best_action().is_some());
+
+        let mcts = search.stop().unwrap();
+        assert!(mcts.iterations() >= 100);
+        assert!(mcts.best_action().is_some());
+    }
+
+    #[test]
+    fn test_ponder() {
+        let mut game = MiniGame::new();
+        let actions = game.allowed_actions();
+        game.make_move(&actions[2]);
+
+        // Pondering on the expected reply continues after a hit...
+        let search = BackgroundSearch::ponder(MCTS::new(&game, 2), &game, &actions[0], 1.).unwrap();
+        assert_eq!(search.pondering(), Some(actions[0]));
+        let mut hit = game.clone();
+        hit.make_move(&actions[0]);
+        let search = search.opponent_moved(&actions[0], &hit).unwrap();
+        assert_eq!(search.pondering(), None);
+        while search.iterations() < 10 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(search.stop().unwrap().best_action().is_some());
+
+        // ...and restarts on the actual position after a miss.
+        let search = BackgroundSearch::ponder(MCTS::new(&game, 2), &game, &actions[0], 1.).unwrap();
+        let mut miss = game.clone();
+        miss.make_move(&actions[2]);
+        let search = search.opponent_moved(&actions[2], &miss).unwrap();
+        while search.iterations() < 10 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(search.stop().unwrap().best_action(), None);
+    }
+
+    #[test]
+    fn test_error() {
+        let game = Tweaked { reject_moves: true, .. Tweaked::new(MiniGame::new()) };
+        let search = BackgroundSearch::start(MCTS::new(&game, 2), 1.);
+        while !search.is_failed() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let error = search.error().unwrap();
+        assert!(matches!(error, GameError::IllegalMove(_)));
+        assert_eq!(search.best_action(), None);
+        assert_eq!(search.stop().unwrap_err(), error);
+    }
+}
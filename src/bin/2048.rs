@@ -5,8 +5,9 @@ extern crate mcts;
 
 use argparse::{ArgumentParser, StoreTrue, Store};
 
-use mcts::mcts::{Game, MCTS};
-use mcts::twofortyeight::TwoFortyEight;
+use mcts::mcts::{Game, MCTS, RootPolicy, SearchSettings, TreePolicy};
+use mcts::observer::SearchEvent;
+use mcts::twofortyeight::{Action, TwoFortyEight};
 
 #[cfg_attr(test, allow(dead_code))]
 fn main() {
@@ -14,6 +15,13 @@ fn main() {
     let mut verbose = false;
     let mut time_per_move = 1.0;
     let mut ensemble_size = 10;
+    let mut exploration = 1.0;
+    let mut max_playout_depth = 0;
+    let mut sp_mcts = false;
+    let mut sp_mcts_d = 10000.;
+    let mut max_backup = false;
+    let mut seed = 0;
+    let mut root_policy = "uct".to_string();
 
     {
         let mut ap = ArgumentParser::new();
@@ -27,6 +35,27 @@ fn main() {
         ap.refer(&mut ensemble_size)
             .add_option(&["--ensemble_size", "-e"], Store,
             "Ensemble size.");
+        ap.refer(&mut exploration)
+            .add_option(&["--exploration", "-c"], Store,
+            "Exploration constant of the tree policy.");
+        ap.refer(&mut max_playout_depth)
+            .add_option(&["--max_playout_depth", "-d"], Store,
+            "Maximum number of moves per random playout (0: play until the end).");
+        ap.refer(&mut sp_mcts)
+            .add_option(&["--sp-mcts"], StoreTrue,
+            "Use single-player MCTS selection with a variance term");
+        ap.refer(&mut sp_mcts_d)
+            .add_option(&["--sp-mcts-d"], Store,
+            "Constant D of the SP-MCTS variance term");
+        ap.refer(&mut max_backup)
+            .add_option(&["--max-backup"], StoreTrue,
+            "Back up the best reward seen instead of the mean (implies --sp-mcts)");
+        ap.refer(&mut root_policy)
+            .add_option(&["--root-policy"], Store,
+            "Root action selection: uct, halving or gumbel");
+        ap.refer(&mut seed)
+            .add_option(&["--seed", "-s"], Store,
+            "Seed for the random decisions of the search.");
         ap.refer(&mut repeats)
             .add_option(&["--repeat", "-r"], Store,
             "Numer of games to play.");
@@ -36,8 +65,30 @@ fn main() {
     println!("Playing 2048\n");
     println!("Time per move: {} s", time_per_move);
     println!("Ensemble size: {}", ensemble_size);
+    println!("Exploration:   {}", exploration);
     println!("");
 
+    let settings = SearchSettings {
+        max_playout_depth: if max_playout_depth > 0 { Some(max_playout_depth) } else { None },
+        tree_policy: if sp_mcts || max_backup {
+            TreePolicy::SpMcts { d: sp_mcts_d, max_backup }
+        } else {
+            TreePolicy::Uct
+        },
+        root_policy: match root_policy.as_ref() {
+            "uct" => RootPolicy::Tree,
+            "halving" => RootPolicy::SequentialHalving,
+            "gumbel" => RootPolicy::Gumbel { m: 4, c_visit: 50., c_scale: 1. },
+            _ => {
+                println!("Unknown root policy: {}", root_policy);
+                return;
+            }
+        },
+        .. SearchSettings::default()
+    };
+    println!("Tree policy: {:?}", settings.tree_policy);
+    println!("Root policy: {:?}", settings.root_policy);
+
     // Summary statistics
     let mut sum_moves = 0.;
     let mut sum_score = 0.;
@@ -45,14 +96,30 @@ fn main() {
     let mut sum_score_sq = 0.;
 
     // Play repeat games in total...
-    for _ in 0..repeats {
+    for r in 0..repeats {
         // Create a game and a MCTS solver
         let mut game = TwoFortyEight::new();
-        let mut mcts = MCTS::new(&game, ensemble_size);
+        let mut mcts = MCTS::with_seed(&game, ensemble_size, seed + r);
+        mcts.set_settings(settings);
+        if verbose {
+            // Live "thinking" output
+            mcts.add_observer(|event: &SearchEvent<Action>| {
+                match *event {
+                    SearchEvent::BestActionChanged { iterations, best, value, .. } =>
+                        println!("  [{:>6}] best: {:?} ({:.1})", iterations, best, value),
+                    SearchEvent::Finished { iterations, elapsed_s, .. } =>
+                        println!("  {} iterations in {:.2} s", iterations, elapsed_s),
+                    _ => {}
+                }
+            });
+        }
 
         println!("{}", game);
         loop {
-            mcts.search_time(time_per_move, 1.0);
+            if let Err(err) = mcts.search_time(time_per_move, exploration) {
+                println!("Search failed: {}", err);
+                break;
+            }
 
             if verbose {
                 println!("{:?}", mcts.tree_statistics());
@@ -80,8 +147,8 @@ fn main() {
         let frepeats = repeats as f32;
         let avg_moves = sum_moves / frepeats;
         let avg_score = sum_score / frepeats;
-        let avg_moves_err = ((sum_moves_sq - sum_moves.powi(2)) / ((frepeats-1.) * frepeats)).sqrt();
-        let avg_score_err = ((sum_score_sq - sum_score.powi(2)) / ((frepeats-1.) * frepeats)).sqrt();
+        let avg_moves_err = ((sum_moves_sq - sum_moves.powi(2) / frepeats) / ((frepeats-1.) * frepeats)).sqrt();
+        let avg_score_err = ((sum_score_sq - sum_score.powi(2) / frepeats) / ((frepeats-1.) * frepeats)).sqrt();
 
         println!("Played {} games.", repeats);
         println!("  Average # moves: {} (+/- {})", avg_moves, avg_moves_err);
@@ -0,0 +1,505 @@
+//!
+//! Implementation of Connect Four on a bitboard.
+//!
+//! Two players, `x` (player 0, moves first) and `o` (player 1), take turns
+//! dropping a stone into one of the columns; it falls to the lowest free
+//! cell. Whoever first gets four stones in a row horizontally, vertically
+//! or diagonally wins and gains a reward of 1; the opponent gets -1. A full
+//! board is a draw worth 0 to both. The standard board has 7 columns and
+//! 6 rows, but any size with `width * (height + 1) <= 64` is supported.
+//!
+//! Every column takes `height + 1` bits of a `u64`, bottom to top; the top
+//! bit of each column stays empty so that lines cannot wrap around into the
+//! next column and four in a row can be detected with a few shifts [1].
+//!
+//! Positions can be given as the sequence of columns played, numbered from
+//! 1 as in [1], e.g. `"4453"`.
+//!
+//! [1] J. Tromp: John's Connect Four Playground (https://tromp.github.io/c4/c4.html)
+//! [2] P. Pons: Solving Connect 4: how to build a perfect AI (2019)
+//!
+
+use std::fmt;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Action {
+    column: u8
+}
+impl GameAction for Action {}
+
+impl Action {
+    /// Drop a stone into `column`, numbered from 0 at the left.
+    pub fn new(column: usize) -> Action {
+        Action { column: column as u8 }
+    }
+
+    pub fn column(&self) -> usize {
+        self.column as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectFour {
+    width: u8,
+    height: u8,
+    stones: [u64; 2],                   // cells taken by x and o
+    moves: u8,
+    won: bool,                          // the last move made four in a row
+}
+
+impl ConnectFour {
+    /// The standard 7x6 board.
+    pub fn new() -> ConnectFour {
+        ConnectFour::with_size(7, 6).unwrap()
+    }
+
+    /// An empty board with `width` columns and `height` rows.
+    pub fn with_size(width: usize, height: usize) -> Result<ConnectFour, GameError> {
+        if width == 0 || height == 0 || width * (height + 1) > 64 {
+            return Err(GameError::Other(format!("a {}x{} board does not fit into 64 bits", width, height)));
+        }
+        Ok(ConnectFour {
+            width: width as u8,
+            height: height as u8,
+            stones: [0; 2],
+            moves: 0,
+            won: false,
+        })
+    }
+
+    /// Play a sequence of columns numbered from 1, e.g. `"4453"`.
+    pub fn from_moves(width: usize, height: usize, moves: &str) -> Result<ConnectFour, GameError> {
+        let mut game = ConnectFour::with_size(width, height)?;
+        for c in moves.chars() {
+            let column = c.to_digit(10)
+                    .filter(|&d| d >= 1)
+                    .ok_or_else(|| GameError::Other(format!("unexpected {:?} in {:?}", c, moves)))?;
+            game.try_make_move(&Action::new(column as usize - 1))?;
+        }
+        Ok(game)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+
+    /// Number of stones on the board.
+    pub fn moves(&self) -> usize {
+        self.moves as usize
+    }
+
+    /// The player who made four in a row, if any.
+    pub fn winner(&self) -> Option<usize> {
+        if self.won {
+            Some((self.moves() + 1) % 2)
+        } else {
+            None
+        }
+    }
+
+    fn occupied(&self) -> u64 {
+        self.stones[0] | self.stones[1]
+    }
+
+    fn cell(&self, column: usize, row: usize) -> u64 {
+        1 << (column * (self.height() + 1) + row)
+    }
+
+    fn column_mask(&self, column: usize) -> u64 {
+        ((1 << self.height()) - 1) << (column * (self.height() + 1))
+    }
+
+    fn is_full(&self, column: usize) -> bool {
+        self.occupied() & self.cell(column, self.height() - 1) != 0
+    }
+
+    /// Bit mask of the columns that are not full.
+    fn open_columns(&self) -> u64 {
+        (0..self.width()).filter(|&column| !self.is_full(column))
+            .fold(0, |open, column| open | 1 << column)
+    }
+
+    /// Whether `stones` contain four in a row.
+    fn has_four(&self, stones: u64) -> bool {
+        let h = self.height() as u32;
+        // vertical, horizontal and both diagonals
+        [1, h + 1, h, h + 2].iter().any(|&shift| {
+            let pairs = stones & (stones >> shift);
+            pairs & (pairs >> (2 * shift)) != 0
+        })
+    }
+}
+
+impl Default for ConnectFour {
+    fn default() -> ConnectFour {
+        ConnectFour::new()
+    }
+}
+
+impl fmt::Display for ConnectFour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in (0..self.height()).rev() {
+            for column in 0..self.width() {
+                let cell = self.cell(column, row);
+                let c = if self.stones[0] & cell != 0 {
+                    'x'
+                } else if self.stones[1] & cell != 0 {
+                    'o'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game<Action> for ConnectFour {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        if self.won {
+            return;
+        }
+        actions.extend((0..self.width())
+            .filter(|&column| !self.is_full(column))
+            .map(Action::new));
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.won || self.moves() == self.width() * self.height()
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        if self.won {
+            return None;
+        }
+        let open = self.open_columns();
+        if open == 0 {
+            return None;
+        }
+        let mut k = rng.gen_range(0, open.count_ones());
+        let mut column = 0;
+        loop {
+            if open & (1 << column) != 0 {
+                if k == 0 {
+                    return Some(Action::new(column));
+                }
+                k -= 1;
+            }
+            column += 1;
+        }
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        let column = action.column();
+        if column >= self.width() || self.is_full(column) || self.won {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        // Adding the bottom cell to the column's stones carries into the lowest free cell
+        let taken = self.occupied() & self.column_mask(column);
+        let free = (taken + self.cell(column, 0)) & self.column_mask(column);
+        let free = free & !taken;
+
+        let player = self.current_player();
+        self.stones[player] |= free;
+        self.moves += 1;
+        self.won = self.has_four(self.stones[player]);
+        Ok(())
+    }
+
+    /// Reward of `x`.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn supports_unmake(&self) -> bool {
+        true
+    }
+
+    fn unmake_move(&mut self, action: &Action) {
+        let taken = self.occupied() & self.column_mask(action.column());
+        let top = 1 << (63 - taken.leading_zeros());
+        self.moves -= 1;
+        let player = self.current_player();
+        self.stones[player] &= !top;
+        self.won = false;
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.moves() % 2
+    }
+
+    fn reward_for(&self, player: usize) -> f32 {
+        match self.winner() {
+            Some(winner) if winner == player => 1.,
+            Some(_) => -1.,
+            None => 0.,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use test::Bencher;
+
+    use agent::{Budget, MctsAgent, RandomAgent};
+    use arena::Arena;
+    use connectfour::*;
+    use mcts::*;
+    use utils::seeded_rng;
+
+    fn position(moves: &str) -> ConnectFour {
+        ConnectFour::from_moves(7, 6, moves).unwrap()
+    }
+
+    /// Exact value of the game for the player to move.
+    fn solve(game: &mut ConnectFour, cache: &mut HashMap<[u64; 2], f32>) -> f32 {
+        if game.is_terminal() {
+            return game.reward_for(game.current_player());
+        }
+        if let Some(&value) = cache.get(&game.stones) {
+            return value;
+        }
+        let mut best = -1.;
+        for action in game.allowed_actions() {
+            game.make_move(&action);
+            best = f32::max(best, -solve(game, cache));
+            game.unmake_move(&action);
+            if best == 1. {
+                break;
+            }
+        }
+        cache.insert(game.stones, best);
+        best
+    }
+
+    /// Exact score for the player to move as used by the test sets of [2]:
+    /// 0 for a draw, otherwise positive if the player to move wins and
+    /// negative if they lose, the larger the sooner the game is won.
+    fn score(game: &mut ConnectFour, cache: &mut HashMap<[u64; 2], i32>) -> i32 {
+        if let Some(&score) = cache.get(&game.stones) {
+            return score;
+        }
+        let actions = game.allowed_actions();
+        if actions.is_empty() {
+            return 0;
+        }
+        let stones_left = (game.width() * game.height() - game.moves()) as i32;
+        for action in &actions {
+            let mut next = *game;
+            next.make_move(action);
+            if next.winner().is_some() {
+                return (stones_left + 1) / 2;
+            }
+        }
+        let mut best = i32::MIN;
+        for action in &actions {
+            game.make_move(action);
+            best = best.max(-score(game, cache));
+            game.unmake_move(action);
+        }
+        cache.insert(game.stones, best);
+        best
+    }
+
+    fn mcts_action(game: &ConnectFour, iterations: usize) -> Action {
+        let mut mcts = MCTS::with_seed(game, 1, 0);
+        mcts.search(iterations, 1.).unwrap();
+        mcts.best_action().unwrap()
+    }
+
+    #[test]
+    fn test_with_size() {
+        assert!(ConnectFour::with_size(8, 7).is_ok());
+        assert!(ConnectFour::with_size(9, 7).is_err());
+        assert!(ConnectFour::with_size(0, 6).is_err());
+
+        let game = ConnectFour::new();
+        assert_eq!((game.width(), game.height()), (7, 6));
+        assert_eq!(game.allowed_actions().len(), 7);
+    }
+
+    #[test]
+    fn test_from_moves() {
+        let game = position("4453");
+        assert_eq!(format!("{}", game), ".......\n.......\n.......\n.......\n...o...\n..oxx..\n");
+        assert_eq!(game.moves(), 4);
+        assert_eq!(game.current_player(), 0);
+
+        assert!(ConnectFour::from_moves(7, 6, "48").is_err());
+        assert!(ConnectFour::from_moves(7, 6, "40").is_err());
+        assert!(ConnectFour::from_moves(7, 6, "1111111").is_err());
+        assert!(ConnectFour::from_moves(7, 6, "1212121").unwrap().try_make_move(&Action::new(0)).is_err());
+    }
+
+    #[test]
+    fn test_four_in_a_row() {
+        // vertical, horizontal and both diagonals
+        for moves in &["1212121", "1122334", "12234334464", "76654554424"] {
+            let game = position(moves);
+            assert_eq!(game.winner(), Some(0), "{}\n{}", moves, game);
+            assert!(game.is_terminal());
+            assert!(game.allowed_actions().is_empty());
+            assert_eq!(game.random_action(&mut seeded_rng(0, 0)), None);
+            assert_eq!((game.reward_for(0), game.reward_for(1)), (1., -1.));
+        }
+        // o wins; three in a row do not count
+        assert_eq!(position("12121232").winner(), Some(1));
+        assert_eq!(position("112233").winner(), None);
+        // no wrap-around into the next column
+        let game = ConnectFour::new();
+        assert!(!game.has_four(game.cell(0, 4) | game.cell(0, 5) | game.cell(1, 0) | game.cell(1, 1)));
+
+        // a full board without four in a row
+        let mut game = ConnectFour::with_size(4, 1).unwrap();
+        for column in 0..4 {
+            game.make_move(&Action::new(column));
+        }
+        assert!(game.is_terminal());
+        assert_eq!(game.winner(), None);
+        assert_eq!(game.decided_reward(), Some(0.));
+    }
+
+    #[test]
+    fn test_unmake_move() {
+        let mut game = position("44444433");
+        let before = game;
+        for action in game.allowed_actions() {
+            game.make_move(&action);
+            game.unmake_move(&action);
+            assert_eq!(game, before);
+        }
+        let mut won = position("121212");
+        won.make_move(&Action::new(0));
+        won.unmake_move(&Action::new(0));
+        assert_eq!(won, position("121212"));
+    }
+
+    #[test]
+    fn test_random_action() {
+        let game = position("4444441");
+        let mut rng = seeded_rng(0, 0);
+        for _ in 0..100 {
+            assert_ne!(game.random_action(&mut rng), Some(Action::new(3)));
+        }
+    }
+
+    #[test]
+    fn test_solved_boards() {
+        // 4x4 is a draw with perfect play
+        let mut game = ConnectFour::with_size(4, 4).unwrap();
+        assert_eq!(solve(&mut game, &mut HashMap::new()), 0.);
+    }
+
+    #[test]
+    fn test_known_positions() {
+        // End game positions with their published scores from the test set
+        // Test_L3_R1 of [2]
+        for &(moves, expected) in &[
+            ("2252576253462244111563365343671351441", -1),
+            ("23163416124767223154467471272416755633", 0),
+            ("65214673556155731566316327373221417", -1),
+        ] {
+            let mut game = position(moves);
+            assert_eq!(score(&mut game, &mut HashMap::new()), expected, "{}", moves);
+            let value = solve(&mut game, &mut HashMap::new());
+            assert_eq!(value, expected.signum() as f32, "{}", moves);
+        }
+    }
+
+    #[test]
+    fn test_forced_wins() {
+        // Win immediately rather than blocking
+        assert_eq!(mcts_action(&position("121212"), 1000), Action::new(0));
+        // Block the only threat
+        assert_eq!(mcts_action(&position("414151"), 1000), Action::new(0));
+
+        // An open three on the bottom row wins two moves later
+        let game = position("4455");
+        let action = mcts_action(&game, 3000);
+        assert!(action == Action::new(2) || action == Action::new(5), "{:?}", action);
+        let mut next = game;
+        next.make_move(&action);
+        for reply in next.allowed_actions() {
+            let mut after = next;
+            after.make_move(&reply);
+            assert!(after.allowed_actions().iter().any(|win| {
+                let mut end = after;
+                end.make_move(win);
+                end.winner() == Some(0)
+            }), "{:?}\n{}", reply, after);
+        }
+    }
+
+    #[test]
+    fn test_against_random() {
+        let arena = Arena::new(&ConnectFour::new());
+        let mut mcts = MctsAgent::new(1, Budget::Iterations(300), 1.);
+        let mut random = RandomAgent::new();
+        let results = arena.run(&mut [&mut mcts, &mut random], 4).unwrap();
+        assert_eq!(results[0].wins, 4);
+    }
+
+    #[bench]
+    fn bench_playout(b: &mut Bencher) {
+        let game = ConnectFour::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
+    }
+
+    #[bench]
+    fn bench_fill_actions(b: &mut Bencher) {
+        let game = position("4444441");
+        let mut actions = Vec::new();
+        b.iter(|| {
+            game.fill_actions(&mut actions);
+            actions.len()
+        });
+    }
+
+    #[bench]
+    fn bench_search(b: &mut Bencher) {
+        let game = ConnectFour::new();
+        let mut mcts = MCTS::new(&game, 1);
+        b.iter(|| mcts.search(10, 1.).unwrap())
+    }
+}
+
@@ -0,0 +1,289 @@
+//!
+//! Elo ratings with error bars and the sequential probability ratio test.
+//!
+//! Game results are counted from the point of view of one agent: a win
+//! scores 1 point, a draw 1/2 and a loss 0. The SPRT uses the normal
+//! approximation of the trinomial model common in engine testing, which
+//! allows a match to stop as soon as one of the hypotheses is accepted.
+//!
+//! [1] A. Wald: Sequential Tests of Statistical Hypotheses (Ann. Math. Statist., 1945)
+//! [2] D. R. Hunter: MM algorithms for generalized Bradley-Terry models (Ann. Statist., 2004)
+//!
+
+use std::f32;
+
+/// Expected score against an opponent rated `elo` points lower.
+pub fn expected_score(elo: f32) -> f32 {
+    1. / (1. + 10f32.powf(-elo / 400.))
+}
+
+/// Elo difference corresponding to an expected `score` in [0, 1].
+pub fn elo_difference(score: f32) -> f32 {
+    -400. * (1. / score - 1.).log10()
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+/// Wins, draws and losses of one agent against another.
+pub struct Score {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Score {
+    /// Count a game in which `points` (1, 1/2 or 0) were scored.
+    pub fn add(&mut self, points: f32) {
+        if points > 0.5 {
+            self.wins += 1;
+        } else if points == 0.5 {
+            self.draws += 1;
+        } else {
+            self.losses += 1;
+        }
+    }
+
+    /// The same games from the opponent's point of view.
+    pub fn reversed(&self) -> Score {
+        Score {
+            wins: self.losses,
+            draws: self.draws,
+            losses: self.wins,
+        }
+    }
+
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn points(&self) -> f32 {
+        self.wins as f32 + 0.5 * self.draws as f32
+    }
+
+    /// Mean score per game.
+    pub fn mean(&self) -> f32 {
+        self.points() / self.games() as f32
+    }
+
+    /// Variance of the score of a single game.
+    pub fn variance(&self) -> f32 {
+        let n = self.games() as f32;
+        let mean = self.mean();
+        (self.wins as f32 * (1. - mean).powi(2)
+            + self.draws as f32 * (0.5 - mean).powi(2)
+            + self.losses as f32 * mean.powi(2)) / n
+    }
+
+    /// Elo difference with a confidence interval, e.g. `z = 1.96` for 95%.
+    ///
+    /// Returns (elo, lower bound, upper bound); without any lost or
+    /// won games the bounds are infinite.
+    pub fn elo(&self, z: f32) -> (f32, f32, f32) {
+        let mean = self.mean();
+        let err = z * (self.variance() / self.games() as f32).sqrt();
+        let bound = |score: f32| elo_difference(score.clamp(0., 1.));
+        (elo_difference(mean), bound(mean - err), bound(mean + err))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// Decision of a sequential probability ratio test.
+pub enum SprtStatus {
+    /// Not enough evidence yet.
+    Continue,
+    /// The Elo difference is `elo0` (or worse).
+    AcceptH0,
+    /// The Elo difference is `elo1` (or better).
+    AcceptH1,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+/// Sequential probability ratio test of H0: elo = `elo0` against
+/// H1: elo = `elo1`, with error rates `alpha` (false H1) and `beta` (false H0).
+pub struct Sprt {
+    pub elo0: f32,
+    pub elo1: f32,
+    pub alpha: f32,
+    pub beta: f32,
+}
+
+impl Sprt {
+    pub fn new(elo0: f32, elo1: f32, alpha: f32, beta: f32) -> Sprt {
+        Sprt { elo0, elo1, alpha, beta }
+    }
+
+    /// Lower and upper bound of the log-likelihood ratio.
+    pub fn bounds(&self) -> (f32, f32) {
+        ((self.beta / (1. - self.alpha)).ln(), ((1. - self.beta) / self.alpha).ln())
+    }
+
+    /// Log-likelihood ratio of H1 against H0 given `score`.
+    ///
+    /// Zero while the observed variance is zero (e.g. after only draws).
+    pub fn llr(&self, score: &Score) -> f32 {
+        let n = score.games() as f32;
+        let variance = if n > 0. { score.variance() } else { 0. };
+        if variance == 0. {
+            return 0.;
+        }
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+        n * (s1 - s0) * (2. * score.mean() - s0 - s1) / (2. * variance)
+    }
+
+    pub fn status(&self, score: &Score) -> SprtStatus {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtStatus::AcceptH1
+        } else if llr <= lower {
+            SprtStatus::AcceptH0
+        } else {
+            SprtStatus::Continue
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+/// Elo rating of an agent within a pool, relative to the pool average.
+pub struct Rating {
+    pub name: String,
+    pub elo: f32,
+    /// Approximate standard error of `elo`.
+    pub error: f32,
+    pub games: usize,
+}
+
+/// Fit Bradley-Terry ratings to the results of a pool of agents.
+///
+/// `scores[i][j]` are the results of agent `i` against agent `j`. Draws
+/// count as half a win for both sides, and every pair that played gets one
+/// virtual draw so that agents without wins or losses get finite ratings.
+/// Errors ignore the covariance between the ratings.
+pub fn fit_ratings(names: &[String], scores: &[Vec<Score>]) -> Vec<Rating> {
+    let n = names.len();
+    let games = |i: usize, j: usize| -> f32 {
+        let played = scores[i][j].games();
+        if played > 0 { played as f32 + 1. } else { 0. }
+    };
+    let points = (0..n)
+            .map(|i| (0..n)
+                .filter(|&j| scores[i][j].games() > 0)
+                .map(|j| scores[i][j].points() + 0.5)
+                .sum::<f32>())
+            .collect::<Vec<_>>();
+
+    // Minorization-maximization updates of the strengths
+    let mut gamma = vec![1f32; n];
+    for _ in 0..1000 {
+        let mut next = (0..n)
+                .map(|i| {
+                    let denominator = (0..n)
+                            .map(|j| games(i, j) / (gamma[i] + gamma[j]))
+                            .sum::<f32>();
+                    if denominator > 0. { points[i] / denominator } else { gamma[i] }
+                })
+                .collect::<Vec<_>>();
+        let mean_log = next.iter().map(|g| g.ln()).sum::<f32>() / n as f32;
+        for g in &mut next {
+            *g /= mean_log.exp();
+        }
+        let change = gamma.iter().zip(&next)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0., f32::max);
+        gamma = next;
+        if change < 1e-6 {
+            break;
+        }
+    }
+
+    let scale = 400. / f32::consts::LN_10;
+    (0..n)
+        .map(|i| {
+            let information = (0..n)
+                    .map(|j| {
+                        let p = gamma[i] / (gamma[i] + gamma[j]);
+                        games(i, j) * p * (1. - p)
+                    })
+                    .sum::<f32>();
+            Rating {
+                name: names[i].clone(),
+                elo: scale * gamma[i].ln(),
+                error: scale / information.sqrt(),
+                games: scores[i].iter().map(|s| s.games()).sum(),
+            }
+        })
+        .collect()
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use elo::*;
+
+    fn score(wins: usize, draws: usize, losses: usize) -> Score {
+        Score { wins, draws, losses }
+    }
+
+    #[test]
+    fn test_elo_difference() {
+        assert_eq!(expected_score(0.), 0.5);
+        assert!((elo_difference(0.75) - 190.85).abs() < 0.01);
+        assert!((elo_difference(expected_score(123.)) - 123.).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_score() {
+        let mut s = Score::default();
+        for &points in &[1., 0.5, 0., 1.] {
+            s.add(points);
+        }
+        assert_eq!(s, score(2, 1, 1));
+        assert_eq!(s.reversed(), score(1, 1, 2));
+        assert_eq!(s.mean(), 0.625);
+
+        let (elo, lower, upper) = score(60, 20, 20).elo(1.96);
+        assert!((elo - elo_difference(0.7)).abs() < 1e-3);
+        assert!(lower < elo && elo < upper);
+        assert!(lower > 0.);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt::new(0., 10., 0.05, 0.05);
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 1e-3);
+        assert_eq!(lower, -upper);
+
+        assert_eq!(sprt.status(&Score::default()), SprtStatus::Continue);
+        assert_eq!(sprt.status(&score(0, 10, 0)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&score(55, 0, 45)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&score(700, 0, 300)), SprtStatus::AcceptH1);
+        assert_eq!(sprt.status(&score(300, 0, 700)), SprtStatus::AcceptH0);
+        assert!(sprt.llr(&score(60, 0, 40)) > sprt.llr(&score(50, 0, 50)));
+    }
+
+    #[test]
+    fn test_fit_ratings() {
+        let names = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let ab = score(300, 0, 100);
+        let bc = score(300, 0, 100);
+        let ac = score(360, 0, 40);
+        let scores = vec![
+            vec![Score::default(), ab, ac],
+            vec![ab.reversed(), Score::default(), bc],
+            vec![ac.reversed(), bc.reversed(), Score::default()],
+        ];
+
+        let ratings = fit_ratings(&names, &scores);
+        assert!(ratings.iter().map(|r| r.elo).sum::<f32>().abs() < 0.1);
+        let diff = ratings[0].elo - ratings[1].elo;
+        assert!((diff - 190.).abs() < 20., "{:?}", ratings);
+        assert!(ratings[1].elo > ratings[2].elo);
+        assert!(ratings.iter().all(|r| r.error > 0. && r.error < 50. && r.games == 800));
+    }
+}
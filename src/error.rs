@@ -0,0 +1,29 @@
+//!
+//! Error type shared by the games and the search algorithms.
+//!
+
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Errors reported by a `Game` instead of aborting the process.
+pub enum GameError {
+    /// The given action (formatted with `{:?}`) is not allowed in the current game state.
+    IllegalMove(String),
+    /// No empty field left to place a new piece on.
+    BoardFull,
+    /// Any other inconsistency detected by a game implementation.
+    Other(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GameError::IllegalMove(ref action) => write!(f, "illegal move: {}", action),
+            GameError::BoardFull => write!(f, "board is full"),
+            GameError::Other(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl error::Error for GameError {}
@@ -0,0 +1,615 @@
+//!
+//! Implementation of Go with area scoring and positional superko.
+//!
+//! Black (`x`, player 0, moves first) and white (`o`, player 1) take turns
+//! placing a stone or passing. Opponent groups left without liberties are
+//! captured; a move that leaves its own group without liberties is suicide
+//! and illegal unless allowed, in which case the group is removed. No move
+//! may recreate an earlier position of the board (positional superko). The
+//! game ends after two consecutive passes.
+//!
+//! Scoring counts the stones of each player plus the empty regions that
+//! only touch their stones (area scoring); white additionally gets the
+//! komi. The winner gains a reward of 1, the loser -1, and a tie is worth 0.
+//!
+//! Random moves never fill the mover's own eyes and pass when nothing else
+//! is left, so random playouts end with both players passing.
+//!
+//! Points are written as a column letter (skipping `i`) and a row number
+//! counted from the bottom, e.g. `a1` for the bottom left corner.
+//!
+
+use std::collections::HashSet;
+use std::fmt;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+
+const COLUMNS: &str = "abcdefghjklmnopqrst";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Action {
+    /// Place a stone on the point `row * size + col`, row 0 being the top.
+    Play(u16),
+    Pass,
+}
+impl GameAction for Action {}
+
+/// Zobrist key of a stone of `color` (1 or 2) on `point`.
+fn zobrist(point: usize, color: u8) -> u64 {
+    // splitmix64 finalizer
+    let mut z = (2 * point as u64 + color as u64).wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Outcome of placing a stone, before changing the board.
+struct Placement {
+    /// Opponent stones to remove.
+    captured: Vec<usize>,
+    /// Own stones to remove after a suicide.
+    suicide: Vec<usize>,
+    /// Hash of the resulting position.
+    hash: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Go {
+    size: usize,
+    komi: f32,
+    suicide_allowed: bool,
+    board: Vec<u8>,                     // 0 empty, 1 black, 2 white
+    to_move: u8,
+    passes: u8,                         // consecutive passes
+    hash: u64,                          // Zobrist hash of the board
+    history: HashSet<u64>,              // hashes of all positions so far
+}
+
+impl Go {
+    /// An empty 9x9 board with komi 7.5.
+    pub fn new() -> Go {
+        Go::with_size(9).unwrap()
+    }
+
+    /// An empty `size` x `size` board (at most 19x19) with komi 7.5.
+    pub fn with_size(size: usize) -> Result<Go, GameError> {
+        if size == 0 || size > COLUMNS.len() {
+            return Err(GameError::Other(format!("board size must be between 1 and {}", COLUMNS.len())));
+        }
+        Ok(Go {
+            size,
+            komi: 7.5,
+            suicide_allowed: false,
+            board: vec![0; size * size],
+            to_move: 0,
+            passes: 0,
+            hash: 0,
+            history: Some(0).into_iter().collect(),
+        })
+    }
+
+    /// A position given row by row from the top (`x` black, `o` white, `.`
+    /// empty; whitespace and `/` are ignored), with `to_move` to play.
+    /// Captures are not resolved; earlier positions are not known for superko.
+    pub fn from_board(board: &str, to_move: usize) -> Result<Go, GameError> {
+        let points = board.chars()
+                .filter(|c| !(c.is_whitespace() || *c == '/'))
+                .map(|c| match c {
+                    'x' | 'X' => Ok(1),
+                    'o' | 'O' => Ok(2),
+                    '.' | '+' => Ok(0),
+                    _ => Err(GameError::Other(format!("unexpected {:?} in {:?}", c, board))),
+                })
+                .collect::<Result<Vec<u8>, _>>()?;
+        let size = (1..=COLUMNS.len()).find(|size| size * size == points.len())
+                .ok_or_else(|| GameError::Other(format!("{} points do not make a square board", points.len())))?;
+        if to_move > 1 {
+            return Err(GameError::Other(format!("no player {}", to_move)));
+        }
+        let mut game = Go::with_size(size)?;
+        for (point, &color) in points.iter().enumerate().filter(|&(_, &c)| c != 0) {
+            game.board[point] = color;
+            game.hash ^= zobrist(point, color);
+        }
+        game.history = Some(game.hash).into_iter().collect();
+        game.to_move = to_move as u8;
+        Ok(game)
+    }
+
+    /// Points added to white's score.
+    pub fn set_komi(&mut self, komi: f32) {
+        self.komi = komi;
+    }
+
+    /// Allow suicide of groups with more than one stone (suicide of a
+    /// single stone recreates the position and is never allowed).
+    pub fn set_suicide_allowed(&mut self, allowed: bool) {
+        self.suicide_allowed = allowed;
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The point in the given row (from the top) and column.
+    pub fn point(&self, row: usize, col: usize) -> Action {
+        Action::Play((row * self.size + col) as u16)
+    }
+
+    /// Parse a move like `"c3"` or `"pass"` for this board.
+    pub fn parse_action(&self, s: &str) -> Result<Action, GameError> {
+        if s == "pass" {
+            return Ok(Action::Pass);
+        }
+        let mut chars = s.chars();
+        let col = chars.next().and_then(|c| COLUMNS.find(c));
+        let row = chars.as_str().parse::<usize>().ok()
+                .filter(|&row| row >= 1 && row <= self.size);
+        match (row, col) {
+            (Some(row), Some(col)) if col < self.size => Ok(self.point(self.size - row, col)),
+            _ => Err(GameError::Other(format!("{:?} is not a point of a {}x{} board", s, self.size, self.size))),
+        }
+    }
+
+    /// Write a move as `"c3"` or `"pass"`.
+    pub fn format_action(&self, action: &Action) -> String {
+        match *action {
+            Action::Play(point) => {
+                let (row, col) = (point as usize / self.size, point as usize % self.size);
+                format!("{}{}", &COLUMNS[col..col + 1], self.size - row)
+            },
+            Action::Pass => "pass".to_string(),
+        }
+    }
+
+    /// Area score of black minus that of white, including the komi.
+    pub fn score(&self) -> f32 {
+        let mut area = [0, 0];
+        let mut seen = vec![false; self.board.len()];
+        for point in 0..self.board.len() {
+            match self.board[point] {
+                0 if !seen[point] => {
+                    // Flood fill the empty region and note which colours it touches
+                    let mut region = vec![point];
+                    let mut touches = [false; 3];
+                    seen[point] = true;
+                    let mut i = 0;
+                    while i < region.len() {
+                        for next in self.neighbours(region[i]) {
+                            let color = self.board[next];
+                            touches[color as usize] = true;
+                            if color == 0 && !seen[next] {
+                                seen[next] = true;
+                                region.push(next);
+                            }
+                        }
+                        i += 1;
+                    }
+                    match (touches[1], touches[2]) {
+                        (true, false) => area[0] += region.len(),
+                        (false, true) => area[1] += region.len(),
+                        _ => {},
+                    }
+                },
+                0 => {},
+                color => area[color as usize - 1] += 1,
+            }
+        }
+        area[0] as f32 - area[1] as f32 - self.komi
+    }
+
+    fn neighbours(&self, point: usize) -> impl Iterator<Item = usize> {
+        let n = self.size;
+        let (row, col) = (point / n, point % n);
+        let up = if row > 0 { Some(point - n) } else { None };
+        let down = if row + 1 < n { Some(point + n) } else { None };
+        let left = if col > 0 { Some(point - 1) } else { None };
+        let right = if col + 1 < n { Some(point + 1) } else { None };
+        up.into_iter().chain(down).chain(left).chain(right)
+    }
+
+    /// Stones of the group at `start`, and whether it has a liberty other
+    /// than `except`.
+    fn group(&self, start: usize, except: usize) -> (Vec<usize>, bool) {
+        let color = self.board[start];
+        let mut stones = vec![start];
+        let mut seen = vec![false; self.board.len()];
+        seen[start] = true;
+        let mut free = false;
+        let mut i = 0;
+        while i < stones.len() {
+            for next in self.neighbours(stones[i]) {
+                let c = self.board[next];
+                if c == 0 && next != except {
+                    free = true;
+                } else if c == color && !seen[next] {
+                    seen[next] = true;
+                    stones.push(next);
+                }
+            }
+            i += 1;
+        }
+        (stones, free)
+    }
+
+    /// Whether `point` is an eye of `color`: all neighbours are its stones
+    /// and the opponent holds at most one diagonal (none at the edge).
+    fn is_eye(&self, point: usize, color: u8) -> bool {
+        if self.board[point] != 0 || self.neighbours(point).any(|next| self.board[next] != color) {
+            return false;
+        }
+        let n = self.size as isize;
+        let (row, col) = (point as isize / n, point as isize % n);
+        let mut diagonals = 0;
+        let mut opponent = 0;
+        for &(dr, dc) in &[(-1, -1), (-1, 1), (1, -1), (1, 1)] {
+            let (r, c) = (row + dr, col + dc);
+            if r >= 0 && r < n && c >= 0 && c < n {
+                diagonals += 1;
+                let stone = self.board[(r * n + c) as usize];
+                if stone != 0 && stone != color {
+                    opponent += 1;
+                }
+            }
+        }
+        opponent == 0 || (diagonals == 4 && opponent == 1)
+    }
+
+    /// What placing a stone of the player to move on `point` would do, or
+    /// `None` if that is illegal.
+    fn placement(&self, point: usize) -> Option<Placement> {
+        if point >= self.board.len() || self.board[point] != 0 {
+            return None;
+        }
+        let own = self.to_move + 1;
+        let opponent = 2 - self.to_move;
+        let mut captured = Vec::new();
+        let mut own_groups = vec![point];
+        let mut has_liberty = false;
+        for next in self.neighbours(point) {
+            match self.board[next] {
+                0 => has_liberty = true,
+                c if c == own => {
+                    let (stones, free) = self.group(next, point);
+                    has_liberty |= free;
+                    own_groups.extend(stones);
+                },
+                _ => if !captured.contains(&next) {
+                    let (stones, free) = self.group(next, point);
+                    if !free {
+                        captured.extend(stones);
+                    }
+                },
+            }
+        }
+
+        let mut hash = self.hash ^ zobrist(point, own);
+        for &stone in &captured {
+            hash ^= zobrist(stone, opponent);
+        }
+        let mut suicide = Vec::new();
+        if !has_liberty && captured.is_empty() {
+            if !self.suicide_allowed {
+                return None;
+            }
+            own_groups.sort();
+            own_groups.dedup();
+            for &stone in &own_groups {
+                hash ^= zobrist(stone, own);
+            }
+            suicide = own_groups;
+        }
+        if self.history.contains(&hash) {
+            return None;
+        }
+        Some(Placement { captured, suicide, hash })
+    }
+}
+
+impl Default for Go {
+    fn default() -> Go {
+        Go::new()
+    }
+}
+
+impl fmt::Display for Go {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..self.size {
+            for col in 0..self.size {
+                let c = match self.board[row * self.size + col] {
+                    1 => 'x',
+                    2 => 'o',
+                    _ => '.',
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game<Action> for Go {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        if self.is_terminal() {
+            return;
+        }
+        actions.extend((0..self.board.len())
+            .filter(|&point| self.placement(point).is_some())
+            .map(|point| Action::Play(point as u16)));
+        actions.push(Action::Pass);
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.passes >= 2
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        if self.is_terminal() {
+            return None;
+        }
+        let own = self.to_move + 1;
+        let mut candidates = (0..self.board.len())
+                .filter(|&point| self.board[point] == 0 && !self.is_eye(point, own))
+                .collect::<Vec<_>>();
+        while !candidates.is_empty() {
+            let point = candidates.swap_remove(rng.gen_range(0, candidates.len()));
+            if self.placement(point).is_some() {
+                return Some(Action::Play(point as u16));
+            }
+        }
+        Some(Action::Pass)
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        if self.is_terminal() {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        match *action {
+            Action::Play(point) => {
+                let point = point as usize;
+                let placement = self.placement(point)
+                        .ok_or_else(|| GameError::IllegalMove(self.format_action(action)))?;
+                self.board[point] = self.to_move + 1;
+                for &stone in placement.captured.iter().chain(&placement.suicide) {
+                    self.board[stone] = 0;
+                }
+                self.hash = placement.hash;
+                self.history.insert(placement.hash);
+                self.passes = 0;
+            },
+            Action::Pass => self.passes += 1,
+        }
+        self.to_move = 1 - self.to_move;
+        Ok(())
+    }
+
+    /// Reward of black.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.to_move as usize
+    }
+
+    /// The sign of the area score for `player`; the current score before
+    /// the end.
+    fn reward_for(&self, player: usize) -> f32 {
+        let score = self.score();
+        let black = if score > 0. { 1. } else if score < 0. { -1. } else { 0. };
+        if player == 0 { black } else { -black }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use test::Bencher;
+
+    use agent::{Budget, MctsAgent, RandomAgent};
+    use arena::Arena;
+    use go::*;
+    use mcts::*;
+    use utils::seeded_rng;
+
+    fn play(game: &mut Go, moves: &str) {
+        for m in moves.split_whitespace() {
+            let action = game.parse_action(m).unwrap();
+            game.try_make_move(&action).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_actions() {
+        let game = Go::new();
+        assert_eq!(game.parse_action("a1").unwrap(), game.point(8, 0));
+        assert_eq!(game.parse_action("j9").unwrap(), game.point(0, 8));
+        assert_eq!(game.parse_action("h3").unwrap(), game.point(6, 7));
+        assert_eq!(game.format_action(&game.point(6, 7)), "h3");
+        assert_eq!(game.parse_action("pass").unwrap(), Action::Pass);
+        assert!(game.parse_action("i1").is_err());
+        assert!(game.parse_action("a10").is_err());
+        assert!(game.parse_action("k1").is_err());
+        assert_eq!(game.allowed_actions().len(), 82);
+    }
+
+    #[test]
+    fn test_captures() {
+        // A single stone in the corner, then a group of two on the edge
+        let mut game = Go::with_size(5).unwrap();
+        play(&mut game, "a1 a2 e5 b1");
+        assert_eq!(format!("{}", game), "....x\n.....\n.....\no....\n.o...\n");
+        play(&mut game, "c1 c2 d1 d2 e4 e1");
+        assert_eq!(format!("{}", game), "....x\n....x\n.....\no.oo.\n.o..o\n");
+
+        // The group of three has only one liberty left
+        let mut game = Go::from_board("\
+            .....\
+            .....\
+            .o...\
+            oxo..\
+            xx.o.", 1).unwrap();
+        play(&mut game, "c1");
+        assert_eq!(format!("{}", game), ".....\n.....\n.o...\no.o..\n..oo.\n");
+    }
+
+    #[test]
+    fn test_suicide() {
+        let board = "\
+            .....\
+            .....\
+            .....\
+            oo...\
+            x.o..";
+        let mut game = Go::from_board(board, 0).unwrap();
+        let b1 = game.parse_action("b1").unwrap();
+        assert!(game.try_make_move(&b1).is_err());
+        assert!(!game.allowed_actions().contains(&b1));
+
+        // With suicide allowed the group is removed
+        game.set_suicide_allowed(true);
+        game.make_move(&b1);
+        assert_eq!(format!("{}", game), ".....\n.....\n.....\noo...\n..o..\n");
+
+        // A single stone suicide would recreate the position
+        let mut game = Go::from_board("....\n....\n.o..\no.o.", 0).unwrap();
+        game.set_suicide_allowed(true);
+        assert!(game.try_make_move(&game.parse_action("b1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_ko() {
+        let board = "\
+            .xo..\
+            xo.o.\
+            .xo..\
+            .....\
+            .....";
+        let mut game = Go::from_board(board, 0).unwrap();
+        play(&mut game, "c4");
+        assert_eq!(format!("{}", game), ".xo..\nx.xo.\n.xo..\n.....\n.....\n");
+
+        // Immediate recapture would repeat the position
+        let recapture = game.parse_action("b4").unwrap();
+        assert!(game.try_make_move(&recapture).is_err());
+        assert!(!game.allowed_actions().contains(&recapture));
+        assert!(game.random_action(&mut seeded_rng(0, 0)) != Some(recapture));
+
+        // After moves elsewhere it is a new position
+        play(&mut game, "e1 d1 b4");
+        assert_eq!(game.board[6], 2);
+    }
+
+    #[test]
+    fn test_passes() {
+        let mut game = Go::with_size(5).unwrap();
+        play(&mut game, "pass c3 pass");
+        assert!(!game.is_terminal());
+        play(&mut game, "pass");
+        assert!(game.is_terminal());
+        assert!(game.allowed_actions().is_empty());
+        assert_eq!(game.random_action(&mut seeded_rng(0, 0)), None);
+        assert!(game.try_make_move(&Action::Pass).is_err());
+    }
+
+    #[test]
+    fn test_area_scoring() {
+        let board = "\
+            .xo..\
+            .xo..\
+            .x.o.\
+            .xo..\
+            .xo..";
+        let mut game = Go::from_board(board, 0).unwrap();
+        // 5 + 5 for black, 5 + 9 for white; c3 is neutral
+        game.set_komi(0.);
+        assert_eq!(game.score(), -4.);
+        assert_eq!((game.reward_for(0), game.reward_for(1)), (-1., 1.));
+
+        game.set_komi(-4.);
+        assert_eq!(game.reward(), 0.);
+        play(&mut game, "pass pass");
+        assert_eq!(game.decided_reward(), Some(0.));
+        assert_eq!(Go::new().score(), -7.5);
+    }
+
+    #[test]
+    fn test_eyes() {
+        let board = "\
+            .x...\
+            x.x..\
+            .x...\
+            .....\
+            ....o";
+        let game = Go::from_board(board, 0).unwrap();
+        assert!(game.is_eye(6, 1));
+        assert!(game.is_eye(0, 1));
+        assert!(!game.is_eye(6, 2));
+        assert!(!game.is_eye(12, 1));
+
+        // Random playouts leave the eyes alone and end with two passes
+        let mut rng = seeded_rng(0, 0);
+        for _ in 0..5 {
+            let end = playout(&Go::new(), None, &mut rng).unwrap();
+            assert!(end.is_terminal());
+            assert!(end.history.len() < 500);
+        }
+    }
+
+    #[test]
+    fn test_against_random() {
+        let arena = Arena::new(&Go::with_size(5).unwrap());
+        let mut mcts = MctsAgent::new(1, Budget::Iterations(100), 1.);
+        let mut random = RandomAgent::new();
+        let results = arena.run(&mut [&mut mcts, &mut random], 2).unwrap();
+        assert_eq!(results[0].wins, 2);
+    }
+
+    #[bench]
+    fn bench_playout(b: &mut Bencher) {
+        let game = Go::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
+    }
+
+    #[bench]
+    fn bench_fill_actions(b: &mut Bencher) {
+        let mut game = Go::new();
+        play(&mut game, "e5 c3 g7 c7 g3");
+        let mut actions = Vec::new();
+        b.iter(|| {
+            game.fill_actions(&mut actions);
+            actions.len()
+        });
+    }
+}
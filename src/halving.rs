@@ -0,0 +1,291 @@
+//!
+//! Sequential Halving at the root of an MCTS tree.
+//!
+//! With small budgets the cumulative regret UCT minimizes at the root is
+//! the wrong objective: we only care about recommending a good action in
+//! the end (simple regret). Sequential Halving spends the budget in rounds;
+//! every round all remaining candidates receive the same number of
+//! iterations and the worse half is dropped. The Gumbel variant first
+//! samples the candidates according to the action priors.
+//!
+//! [1] Z. Karnin, T. Koren, O. Somekh: Almost Optimal Exploration in Multi-Armed Bandits (ICML 2013)
+//! [2] I. Danihelka, A. Guez, J. Schrittwieser, D. Silver: Policy improvement by planning with Gumbel (ICLR 2022)
+//!
+
+use std::f32;
+use std::cmp::{max, Ordering};
+
+use rand::Rng;
+
+use mcts::{Game, GameAction, RootPolicy, SearchSettings, TreeNode};
+
+/// Smallest prior used for Gumbel sampling, keeping the log-prior finite.
+const MIN_PRIOR: f32 = 1e-6;
+
+#[derive(Debug, Clone)]
+/// Schedule distributing a budget of iterations among root actions.
+pub struct SequentialHalving<A: GameAction> {
+    candidates: Vec<(A, f32)>,          // remaining actions with their Gumbel score
+    budget: usize,                      // iterations planned for the whole schedule
+    used: usize,                        // iterations handed out so far
+    rounds_left: usize,                 // rounds including the current one
+    quota: usize,                       // iterations per candidate in this round
+    done: usize,                        // iterations handed out in this round
+}
+
+impl<A: GameAction> SequentialHalving<A> {
+
+    /// Plan `budget` iterations among all children of `root`.
+    pub fn new(root: &TreeNode<A>, budget: usize) -> SequentialHalving<A> {
+        let candidates = root.children().iter()
+                .map(|child| (child.action().expect("Child node without action"), 0.))
+                .collect();
+        SequentialHalving::with_candidates(candidates, budget)
+    }
+
+    /// Plan `budget` iterations among `m` children of `root`, sampled
+    /// without replacement proportional to their `Game::action_prior`.
+    ///
+    /// Sampling uses the Gumbel-top-k trick: every action is scored with
+    /// Gumbel noise plus its log-prior and the `m` best are kept. Priors
+    /// below `MIN_PRIOR`, including zero, count as `MIN_PRIOR`, so such
+    /// actions come last but still fill up the `m` candidates.
+    pub fn gumbel<G: Game<A>, R: Rng>(root: &TreeNode<A>, game: &G, m: usize, budget: usize, rng: &mut R) -> SequentialHalving<A> {
+        let mut candidates = root.children().iter()
+                .map(|child| {
+                    let action = child.action().expect("Child node without action");
+                    let gumbel = -(-rng.gen::<f32>().ln()).ln();
+                    (action, gumbel + game.action_prior(&action).max(MIN_PRIOR).ln())
+                })
+                .collect::<Vec<_>>();
+        sort_descending(&mut candidates);
+        candidates.truncate(max(m, 1));
+        SequentialHalving::with_candidates(candidates, budget)
+    }
+
+    fn with_candidates(candidates: Vec<(A, f32)>, budget: usize) -> SequentialHalving<A> {
+        let rounds = rounds(candidates.len());
+        let mut schedule = SequentialHalving {
+            budget: max(budget, candidates.len() * rounds),
+            candidates,
+            used: 0,
+            rounds_left: rounds,
+            quota: 0,
+            done: 0,
+        };
+        schedule.quota = schedule.round_quota();
+        schedule
+    }
+
+    /// Number of iterations planned for the whole schedule.
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Has the planned budget been handed out?
+    pub fn is_exhausted(&self) -> bool {
+        self.used >= self.budget
+    }
+
+    /// The remaining candidate actions.
+    pub fn candidates(&self) -> Vec<A> {
+        self.candidates.iter().map(|&(action, _)| action).collect()
+    }
+
+    /// Return the root action for the next iteration.
+    ///
+    /// Candidates take turns; once every candidate has received its quota
+    /// for the round, the worse half is dropped, ranking by the values of
+    /// the children of `root`.
+    pub fn next_action(&mut self, root: &TreeNode<A>, settings: &SearchSettings) -> A {
+        if self.candidates.len() > 1 && self.done >= self.quota * self.candidates.len() {
+            self.halve(root, settings);
+        }
+        let action = self.candidates[self.done % self.candidates.len()].0;
+        self.done += 1;
+        self.used += 1;
+        action
+    }
+
+    /// The candidate to recommend at the end of the search.
+    ///
+    /// With the Gumbel root policy this is the remaining candidate with the
+    /// highest Gumbel noise plus log-prior plus sigma(q) [2]; otherwise the
+    /// remaining candidate with the best value.
+    pub fn recommendation(&self, root: &TreeNode<A>, settings: &SearchSettings) -> Option<A> {
+        let mut ranked = self.ranked(root, settings);
+        sort_descending(&mut ranked);
+        ranked.first().map(|&((action, _), _)| action)
+    }
+
+    /// Drop the worse half of the candidates and start the next round.
+    fn halve(&mut self, root: &TreeNode<A>, settings: &SearchSettings) {
+        let mut ranked = self.ranked(root, settings);
+        sort_descending(&mut ranked);
+        ranked.truncate(ranked.len().div_ceil(2));
+
+        self.candidates = ranked.into_iter().map(|(candidate, _)| candidate).collect();
+        self.rounds_left = max(self.rounds_left, 2) - 1;
+        self.done = 0;
+        self.quota = self.round_quota();
+    }
+
+    /// Score the candidates by the values of the children of `root`.
+    fn ranked(&self, root: &TreeNode<A>, settings: &SearchSettings) -> Vec<((A, f32), f32)> {
+        let values = self.candidates.iter()
+                .map(|&(action, _)| child_value(root, &action, settings))
+                .collect::<Vec<_>>();
+
+        match settings.root_policy {
+            RootPolicy::Gumbel { c_visit, c_scale, .. } => {
+                // sigma(q) with values normalized to [0, 1]
+                let min_value = values.iter().cloned().fold(f32::INFINITY, f32::min);
+                let max_value = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                let range = if max_value > min_value { max_value - min_value } else { 1. };
+                let max_visits = root.children().iter()
+                        .map(|child| child.visits())
+                        .fold(0., f32::max);
+                self.candidates.iter().zip(&values)
+                        .map(|(&(action, score), value)| {
+                            let sigma = (c_visit + max_visits) * c_scale * (value - min_value) / range;
+                            ((action, score), score + sigma)
+                        })
+                        .collect()
+            },
+            _ => self.candidates.iter().cloned().zip(values).collect(),
+        }
+    }
+
+    /// Iterations per candidate when the remaining budget is split evenly
+    /// among the remaining rounds.
+    fn round_quota(&self) -> usize {
+        let remaining = self.budget.saturating_sub(self.used);
+        max(remaining / (self.candidates.len() * self.rounds_left), 1)
+    }
+}
+
+/// Number of halving rounds needed to get from `candidates` down to one.
+fn rounds(candidates: usize) -> usize {
+    max(candidates.next_power_of_two().trailing_zeros() as usize, 1)
+}
+
+/// Value of the child of `root` reached by `action`.
+fn child_value<A: GameAction>(root: &TreeNode<A>, action: &A, settings: &SearchSettings) -> f32 {
+    root.children().iter()
+            .find(|child| child.action() == Some(*action))
+            .map(|child| child.value(&settings.tree_policy))
+            .unwrap_or(f32::NEG_INFINITY)
+}
+
+/// Stable sort by score, highest first.
+fn sort_descending<T>(scored: &mut [(T, f32)]) {
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use halving::*;
+    use mcts::*;
+    use minigame::{Action, MiniGame};
+    use utils::seeded_rng;
+
+    /// A root with all actions of `game` expanded.
+    fn expanded_root(game: &MiniGame) -> TreeNode<Action> {
+        let mut rng = seeded_rng(0, 0);
+        let settings = SearchSettings::default();
+        let mut root = TreeNode::new(None);
+        while !root.is_fully_expanded() {
+            root.iteration(&mut game.clone(), 1., &settings, &mut rng).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_rounds() {
+        assert_eq!(rounds(1), 1);
+        assert_eq!(rounds(2), 1);
+        assert_eq!(rounds(3), 2);
+        assert_eq!(rounds(4), 2);
+        assert_eq!(rounds(5), 3);
+    }
+
+    #[test]
+    fn test_sequential_halving() {
+        let game = MiniGame::new();
+        let settings = SearchSettings {
+            root_policy: RootPolicy::SequentialHalving,
+            .. SearchSettings::default()
+        };
+        let mut root = expanded_root(&game);
+        let mut rng = seeded_rng(0, 1);
+
+        // 3 candidates, 2 rounds: 10 iterations each, then 15 for the best two
+        let mut schedule = SequentialHalving::new(&root, 60);
+        let mut visits = HashMap::new();
+        while !schedule.is_exhausted() {
+            let action = schedule.next_action(&root, &settings);
+            root.iteration_via(&action, &mut game.clone(), 1., &settings, &mut rng).unwrap();
+            *visits.entry(action).or_insert(0) += 1;
+        }
+
+        let mut counts = visits.values().cloned().collect::<Vec<_>>();
+        counts.sort();
+        assert_eq!(counts, vec![10, 25, 25]);
+        assert_eq!(schedule.candidates().len(), 2);
+
+        // The next round only keeps the better candidate
+        let action = schedule.next_action(&root, &settings);
+        assert_eq!(schedule.candidates(), vec![action]);
+    }
+
+    #[test]
+    fn test_gumbel() {
+        let game = MiniGame::new();
+        let root = expanded_root(&game);
+        let mut rng = seeded_rng(0, 1);
+
+        let schedule = SequentialHalving::gumbel(&root, &game, 2, 10, &mut rng);
+        assert_eq!(schedule.candidates().len(), 2);
+        assert_eq!(schedule.budget(), 10);
+
+        // Without the value term the highest Gumbel score is recommended,
+        // with a large one the best value
+        for &(c_scale, by_value) in &[(0., false), (1000., true)] {
+            let settings = SearchSettings {
+                root_policy: RootPolicy::Gumbel { m: 3, c_visit: 50., c_scale },
+                .. SearchSettings::default()
+            };
+            let schedule = SequentialHalving::gumbel(&root, &game, 3, 10, &mut rng);
+            let recommended = schedule.recommendation(&root, &settings).unwrap();
+            if by_value {
+                let best_value = schedule.candidates().iter()
+                        .map(|action| child_value(&root, action, &settings))
+                        .fold(f32::NEG_INFINITY, f32::max);
+                assert_eq!(child_value(&root, &recommended, &settings), best_value);
+            } else {
+                assert_eq!(recommended, schedule.candidates()[0]);
+            }
+        }
+
+        // A tiny budget still gives every candidate one iteration per round
+        let schedule = SequentialHalving::gumbel(&root, &game, 16, 0, &mut rng);
+        assert_eq!(schedule.candidates().len(), 3);
+        assert_eq!(schedule.budget(), 6);
+
+        // Moves that overshoot have a prior of 0 but a finite score
+        let mut game = MiniGame::new();
+        let actions = game.allowed_actions();
+        game.make_move(&actions[1]);
+        game.make_move(&actions[0]);
+        assert_eq!(game.action_prior(&actions[2]), 0.);
+        let root = expanded_root(&game);
+        let schedule = SequentialHalving::gumbel(&root, &game, 3, 10, &mut rng);
+        assert_eq!(schedule.candidates().len(), 3);
+        assert!(schedule.candidates.iter().all(|&(_, score)| score.is_finite()));
+        assert_eq!(schedule.candidates().last(), Some(&actions[2]));
+    }
+}
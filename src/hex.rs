@@ -0,0 +1,509 @@
+//!
+//! Implementation of Hex on a rhombic board of configurable size.
+//!
+//! Player 0 (`x`) tries to connect the top and bottom edges, player 1 (`o`)
+//! the left and right edges; they take turns placing a stone on an empty
+//! cell, and every cell touches six neighbours. A full board always
+//! contains exactly one winning chain, so there are no draws. The winner
+//! gains a reward of 1, the loser -1.
+//!
+//! Chains are tracked with a union-find structure with four virtual nodes
+//! for the edges; a player has won once the nodes of both their edges are
+//! in the same set. Since sets cannot be split again, moves cannot be
+//! unmade.
+//!
+//! With the swap rule, the second player may answer the first move by
+//! taking over that stone instead of placing one: it is mirrored across
+//! the long diagonal so that it serves their own edges.
+//!
+//! Cells are written as a column letter and a row number, e.g. `a1` for the
+//! top left and `k11` for the bottom right cell of the standard 11x11 board.
+//!
+
+use std::fmt;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+
+const MAX_SIZE: usize = 26;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Action {
+    /// Place a stone on the cell `row * size + col`.
+    Place(u16),
+    /// Take over the first stone (second move only, with the swap rule).
+    Swap,
+}
+impl GameAction for Action {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hex {
+    size: usize,
+    swap_rule: bool,
+    cells: Vec<u8>,                     // 0 empty, 1 x, 2 o
+    parent: Vec<u16>,                   // union-find over the cells and the four edges
+    to_move: u8,
+    moves: u16,
+    winner: Option<u8>,
+}
+
+impl Hex {
+    /// The standard 11x11 board without the swap rule.
+    pub fn new() -> Hex {
+        Hex::with_size(11).unwrap()
+    }
+
+    /// An empty `size` x `size` board (at most 26x26).
+    pub fn with_size(size: usize) -> Result<Hex, GameError> {
+        if size == 0 || size > MAX_SIZE {
+            return Err(GameError::Other(format!("board size must be between 1 and {}", MAX_SIZE)));
+        }
+        let cells = size * size;
+        Ok(Hex {
+            size,
+            swap_rule: false,
+            cells: vec![0; cells],
+            parent: (0..cells as u16 + 4).collect(),
+            to_move: 0,
+            moves: 0,
+            winner: None,
+        })
+    }
+
+    /// Play a sequence of moves separated by whitespace, e.g. `"a1 swap c3"`.
+    pub fn from_moves(size: usize, swap_rule: bool, moves: &str) -> Result<Hex, GameError> {
+        let mut game = Hex::with_size(size)?;
+        game.set_swap_rule(swap_rule);
+        for action in moves.split_whitespace() {
+            let action = game.parse_action(action)?;
+            game.try_make_move(&action)?;
+        }
+        Ok(game)
+    }
+
+    /// Allow the second player to swap (must be set before the second move).
+    pub fn set_swap_rule(&mut self, swap_rule: bool) {
+        self.swap_rule = swap_rule;
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of stones placed; a swap counts as a move.
+    pub fn moves(&self) -> usize {
+        self.moves as usize
+    }
+
+    /// The player who connected their edges, if any.
+    pub fn winner(&self) -> Option<usize> {
+        self.winner.map(|w| w as usize)
+    }
+
+    /// The cell in the given row and column.
+    pub fn cell(&self, row: usize, col: usize) -> Action {
+        Action::Place((row * self.size + col) as u16)
+    }
+
+    /// Parse a move like `"c3"` or `"swap"` for this board.
+    pub fn parse_action(&self, s: &str) -> Result<Action, GameError> {
+        if s == "swap" {
+            return Ok(Action::Swap);
+        }
+        let mut chars = s.chars();
+        let col = chars.next()
+                .filter(|c| c.is_ascii_lowercase())
+                .map(|c| c as usize - 'a' as usize);
+        let row = chars.as_str().parse::<usize>().ok()
+                .and_then(|row| row.checked_sub(1));
+        match (row, col) {
+            (Some(row), Some(col)) if row < self.size && col < self.size => Ok(self.cell(row, col)),
+            _ => Err(GameError::Other(format!("{:?} is not a cell of a {}x{} board", s, self.size, self.size))),
+        }
+    }
+
+    /// Write a move as `"c3"` or `"swap"`.
+    pub fn format_action(&self, action: &Action) -> String {
+        match *action {
+            Action::Place(cell) => {
+                let (row, col) = (cell as usize / self.size, cell as usize % self.size);
+                format!("{}{}", (b'a' + col as u8) as char, row + 1)
+            },
+            Action::Swap => "swap".to_string(),
+        }
+    }
+
+    fn swap_allowed(&self) -> bool {
+        self.swap_rule && self.moves == 1
+    }
+
+    fn find(&mut self, mut node: usize) -> usize {
+        while self.parent[node] as usize != node {
+            let grandparent = self.parent[self.parent[node] as usize];
+            self.parent[node] = grandparent;
+            node = grandparent as usize;
+        }
+        node
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a] = b as u16;
+    }
+
+    /// Put a stone of `player` on `cell` and join it with its chains.
+    fn place(&mut self, cell: usize, player: usize) {
+        let n = self.size;
+        let edges = n * n;
+        let (row, col) = (cell / n, cell % n);
+        self.cells[cell] = player as u8 + 1;
+
+        let neighbours = [
+            (row.wrapping_sub(1), col), (row.wrapping_sub(1), col + 1),
+            (row, col.wrapping_sub(1)), (row, col + 1),
+            (row + 1, col.wrapping_sub(1)), (row + 1, col),
+        ];
+        for &(r, c) in &neighbours {
+            if r < n && c < n && self.cells[r * n + c] == player as u8 + 1 {
+                self.union(cell, r * n + c);
+            }
+        }
+        let (first, last) = if player == 0 { (row, edges) } else { (col, edges + 2) };
+        if first == 0 {
+            self.union(cell, last);
+        }
+        if first == n - 1 {
+            self.union(cell, last + 1);
+        }
+        if self.find(last) == self.find(last + 1) {
+            self.winner = Some(player as u8);
+        }
+    }
+}
+
+impl Default for Hex {
+    fn default() -> Hex {
+        Hex::new()
+    }
+}
+
+impl fmt::Display for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..self.size {
+            write!(f, "{}", " ".repeat(row))?;
+            for col in 0..self.size {
+                let c = match self.cells[row * self.size + col] {
+                    1 => 'x',
+                    2 => 'o',
+                    _ => '.',
+                };
+                write!(f, "{}{}", if col > 0 { " " } else { "" }, c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game<Action> for Hex {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        if self.winner.is_some() {
+            return;
+        }
+        actions.extend(self.cells.iter().enumerate()
+            .filter(|&(_, &c)| c == 0)
+            .map(|(cell, _)| Action::Place(cell as u16)));
+        if self.swap_allowed() {
+            actions.push(Action::Swap);
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.winner.is_some()
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        if self.winner.is_some() {
+            return None;
+        }
+        let empty = self.cells.len() - self.cells.iter().filter(|&&c| c != 0).count();
+        let swap = self.swap_allowed() as usize;
+        let k = rng.gen_range(0, empty + swap);
+        if k == empty {
+            return Some(Action::Swap);
+        }
+        self.cells.iter().enumerate()
+            .filter(|&(_, &c)| c == 0)
+            .nth(k)
+            .map(|(cell, _)| Action::Place(cell as u16))
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        let illegal = || GameError::IllegalMove(format!("{:?}", action));
+        if self.winner.is_some() {
+            return Err(illegal());
+        }
+        match *action {
+            Action::Place(cell) => {
+                let cell = cell as usize;
+                if cell >= self.cells.len() || self.cells[cell] != 0 {
+                    return Err(illegal());
+                }
+                let player = self.current_player();
+                self.place(cell, player);
+            },
+            Action::Swap => {
+                if !self.swap_allowed() {
+                    return Err(illegal());
+                }
+                let cell = self.cells.iter().position(|&c| c != 0).ok_or_else(illegal)?;
+                let (row, col) = (cell / self.size, cell % self.size);
+                let mut swapped = Hex::with_size(self.size)?;
+                swapped.swap_rule = self.swap_rule;
+                swapped.place(col * self.size + row, 1);
+                swapped.moves = self.moves;
+                swapped.to_move = self.to_move;
+                *self = swapped;
+            },
+        }
+        self.moves += 1;
+        self.to_move = 1 - self.to_move;
+        Ok(())
+    }
+
+    /// Reward of `x`.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        self.winner.map(|_| self.reward())
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.to_move as usize
+    }
+
+    fn reward_for(&self, player: usize) -> f32 {
+        match self.winner() {
+            Some(winner) if winner == player => 1.,
+            Some(_) => -1.,
+            None => 0.,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use test::Bencher;
+
+    use agent::{Budget, MctsAgent, RandomAgent};
+    use arena::Arena;
+    use hex::*;
+    use mcts::*;
+    use utils::seeded_rng;
+
+    fn position(size: usize, moves: &str) -> Hex {
+        Hex::from_moves(size, false, moves).unwrap()
+    }
+
+    fn perft(game: &Hex, depth: usize) -> usize {
+        if depth == 0 {
+            return 1;
+        }
+        game.allowed_actions().iter()
+            .map(|action| {
+                let mut next = game.clone();
+                next.make_move(action);
+                perft(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    /// Whether `player` connects their edges, by flood fill.
+    fn connects(game: &Hex, player: usize) -> bool {
+        let n = game.size();
+        let stone = player as u8 + 1;
+        let mut seen = vec![false; n * n];
+        let mut todo = (0..n)
+                .map(|i| if player == 0 { i } else { i * n })
+                .filter(|&cell| game.cells[cell] == stone)
+                .collect::<Vec<_>>();
+        while let Some(cell) = todo.pop() {
+            if seen[cell] {
+                continue;
+            }
+            seen[cell] = true;
+            let (row, col) = ((cell / n) as isize, (cell % n) as isize);
+            if (player == 0 && row as usize == n - 1) || (player == 1 && col as usize == n - 1) {
+                return true;
+            }
+            for &(dr, dc) in &[(-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0)] {
+                let (r, c) = (row + dr, col + dc);
+                if r >= 0 && c >= 0 && (r as usize) < n && (c as usize) < n {
+                    let next = r as usize * n + c as usize;
+                    if game.cells[next] == stone {
+                        todo.push(next);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn test_actions() {
+        let game = Hex::new();
+        assert_eq!(game.parse_action("a1").unwrap(), Action::Place(0));
+        assert_eq!(game.parse_action("k11").unwrap(), Action::Place(120));
+        assert_eq!(game.parse_action("c2").unwrap(), game.cell(1, 2));
+        assert_eq!(game.format_action(&game.cell(1, 2)), "c2");
+        assert_eq!(game.parse_action("swap").unwrap(), Action::Swap);
+        assert!(game.parse_action("l1").is_err());
+        assert!(game.parse_action("a12").is_err());
+        assert!(game.parse_action("a0").is_err());
+        assert!(Hex::with_size(0).is_err());
+        assert!(Hex::with_size(27).is_err());
+    }
+
+    #[test]
+    fn test_winner() {
+        // x connects top and bottom along a column
+        let game = position(3, "b1 a1 b2 a2 b3");
+        assert_eq!(game.winner(), Some(0));
+        assert!(game.is_terminal());
+        assert!(game.allowed_actions().is_empty());
+        assert_eq!((game.reward_for(0), game.reward_for(1)), (1., -1.));
+
+        // o connects left and right along the zig-zag of neighbours
+        let game = position(3, "a1 a3 b1 b2 a2 c1");
+        assert_eq!(format!("{}", game), "x x o\n x o .\n  o . .\n");
+        assert_eq!(game.winner(), Some(1));
+        assert_eq!(game.decided_reward(), Some(-1.));
+
+        // a1 and b2 do not touch
+        let game = position(3, "a1 c1 b2 c2 c3");
+        assert_eq!(game.winner(), None);
+        assert_eq!(game.reward(), 0.);
+        assert_eq!(position(1, "a1").winner(), Some(0));
+    }
+
+    #[test]
+    fn test_illegal_moves() {
+        let mut game = position(3, "b2");
+        assert!(game.try_make_move(&game.cell(1, 1)).is_err());
+        assert!(game.try_make_move(&Action::Place(9)).is_err());
+        assert!(game.try_make_move(&Action::Swap).is_err());
+        assert!(Hex::from_moves(3, false, "b1 a1 b2 a2 b3 c3").is_err());
+    }
+
+    #[test]
+    fn test_swap_rule() {
+        let mut game = Hex::from_moves(5, true, "b1").unwrap();
+        assert!(game.allowed_actions().contains(&Action::Swap));
+        game.make_move(&Action::Swap);
+        assert_eq!(format!("{}", game), ". . . . .\n o . . . .\n  . . . . .\n   . . . . .\n    . . . . .\n");
+        assert_eq!(game.current_player(), 0);
+        assert_eq!(game.moves(), 2);
+        assert!(!game.allowed_actions().contains(&Action::Swap));
+        assert!(game.try_make_move(&Action::Swap).is_err());
+
+        // The swapped stone serves o's edges
+        let game = Hex::from_moves(2, true, "b1 swap a1 b2").unwrap();
+        assert_eq!(game.winner(), Some(1));
+        assert!(Hex::from_moves(3, false, "b1 swap").is_err());
+        assert!(Hex::from_moves(3, true, "b1 a1 swap").is_err());
+    }
+
+    #[test]
+    fn test_perft() {
+        // No chain can be complete before the fifth move on 3x3
+        let game = Hex::with_size(3).unwrap();
+        let counts = (1..6).map(|depth| perft(&game, depth)).collect::<Vec<_>>();
+        assert_eq!(counts, vec![9, 72, 504, 3024, 15120]);
+
+        // Positions won by x at depth 5 have no moves, all others four
+        let mut terminal = 0;
+        let mut stack = vec![(game.clone(), 0)];
+        while let Some((game, depth)) = stack.pop() {
+            if depth == 5 {
+                terminal += game.is_terminal() as usize;
+                continue;
+            }
+            for action in game.allowed_actions() {
+                let mut next = game.clone();
+                next.make_move(&action);
+                stack.push((next, depth + 1));
+            }
+        }
+        assert_eq!(perft(&game, 6), (15120 - terminal) * 4);
+
+        // With the swap rule the second move has one more option
+        let mut game = Hex::with_size(3).unwrap();
+        game.set_swap_rule(true);
+        assert_eq!(perft(&game, 2), 9 * 9);
+        assert_eq!(perft(&game, 3), 9 * 8 * 7 + 9 * 8);
+    }
+
+    #[test]
+    fn test_union_find() {
+        // Random games agree with a flood fill and never end in a draw
+        let mut rng = seeded_rng(0, 0);
+        for size in &[3, 7, 11] {
+            for _ in 0..20 {
+                let mut game = Hex::with_size(*size).unwrap();
+                while let Some(action) = game.random_action(&mut rng) {
+                    assert_eq!(game.winner(), None);
+                    game.make_move(&action);
+                    assert_eq!(game.winner() == Some(0), connects(&game, 0), "\n{}", game);
+                    assert_eq!(game.winner() == Some(1), connects(&game, 1), "\n{}", game);
+                }
+                assert!(game.winner().is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_against_random() {
+        let arena = Arena::new(&Hex::with_size(5).unwrap());
+        let mut mcts = MctsAgent::new(1, Budget::Iterations(300), 1.);
+        let mut random = RandomAgent::new();
+        let results = arena.run(&mut [&mut mcts, &mut random], 4).unwrap();
+        assert_eq!(results[0].wins, 4);
+    }
+
+    #[bench]
+    fn bench_playout(b: &mut Bencher) {
+        let game = Hex::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
+    }
+
+    #[bench]
+    fn bench_search(b: &mut Bencher) {
+        let game = Hex::new();
+        let mut mcts = MCTS::new(&game, 1);
+        b.iter(|| mcts.search(10, 1.).unwrap())
+    }
+}
@@ -14,8 +14,26 @@ extern crate test;
 extern crate time;
 extern crate rand;
 
+pub mod agent;
+pub mod arena;
+pub mod background;
 pub mod minigame;
 pub mod twofortyeight;
-// pub mod tictactoe;
+pub mod tictactoe;
+pub mod ultimate_tictactoe;
+pub mod connectfour;
+pub mod othello;
+pub mod hex;
+pub mod go;
+pub mod nim;
+pub mod poker;
+pub mod pig;
+pub mod samegame;
+pub mod elo;
+pub mod error;
+pub mod halving;
 pub mod mcts;
+pub mod nested;
+pub mod observer;
+pub mod tuning;
 pub mod utils;
@@ -1,15 +1,21 @@
 
 use std::fmt;
-use std::i32;
+use std::mem;
 use std::f32;
 use std::fmt::Debug;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::cmp::{min, max};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::cmp::{min, max, Ordering};
 
 use time;
+use rand::{Rng, XorShiftRng};
 
-use utils::{choose_random};
+use error::GameError;
+use halving::SequentialHalving;
+use observer::{SearchEvent, SearchObserver};
+use utils::{choose_random, seeded_rng};
 
 /// A `Game` represets a game state.
 ///
@@ -20,14 +26,141 @@ pub trait Game<A: GameAction> : Clone {
     /// Return a list with all allowed actions given the current game state.
     fn allowed_actions(&self) -> Vec<A>;
 
+    /// Replace the content of `actions` with all allowed actions.
+    ///
+    /// Lets callers reuse a buffer instead of allocating a new `Vec` for
+    /// every call. Defaults to `allowed_actions`.
+    fn fill_actions(&self, actions: &mut Vec<A>) {
+        actions.clear();
+        actions.extend(self.allowed_actions());
+    }
+
+    /// Is the game over, i.e. are there no allowed actions left?
+    fn is_terminal(&self) -> bool {
+        self.allowed_actions().is_empty()
+    }
+
+    /// Pick one of the allowed actions uniformly at random.
+    ///
+    /// Returns `None` if the game is over. Used by random playouts; games
+    /// can override this to avoid enumerating all allowed actions.
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<A> {
+        let actions = self.allowed_actions();
+        if actions.is_empty() {
+            None
+        } else {
+            Some(*choose_random(rng, &actions))
+        }
+    }
+
     /// Change the current game state according to the given action.
     fn make_move(&mut self, action: &A);
 
+    /// Change the current game state according to the given action, reporting
+    /// illegal actions as errors instead of panicking.
+    ///
+    /// The search algorithms only ever call this variant. Defaults to `make_move`.
+    fn try_make_move(&mut self, action: &A) -> Result<(), GameError> {
+        self.make_move(action);
+        Ok(())
+    }
+
     /// Reward for the player when reaching the current game state.
     fn reward(&self) -> f32;
 
     /// Derterminize the game
     fn set_rng_seed(&mut self, seed: u32);
+
+    /// Replace what `observer` cannot see, e.g. the cards of the other
+    /// players, by a sample derived from `seed`.
+    ///
+    /// Every tree of an MCTS ensemble searches its own determinization,
+    /// sampled from the view of the player to move at the root. Defaults to
+    /// `set_rng_seed`, which fixes the outcome of future random events.
+    fn determinize(&mut self, _observer: usize, seed: u32) {
+        self.set_rng_seed(seed);
+    }
+
+    /// Static evaluation of the current game state.
+    ///
+    /// Used to score playouts that are cut off before they reach a
+    /// terminal state. Defaults to the current `reward`.
+    fn evaluate(&self) -> f32 {
+        self.reward()
+    }
+
+    /// Static evaluation of the current game state for `player`.
+    ///
+    /// Scores cut-off playouts in games with several players. Defaults to
+    /// `evaluate` for player 0 and its negation for the others, as in
+    /// two-player zero-sum games.
+    fn evaluate_for(&self, player: usize) -> f32 {
+        if player == 0 {
+            self.evaluate()
+        } else {
+            -self.evaluate()
+        }
+    }
+
+    /// The final reward if the outcome of the game is already decided.
+    ///
+    /// Playouts and tree iterations stop as soon as this returns `Some`.
+    fn decided_reward(&self) -> Option<f32> {
+        None
+    }
+
+    /// Code identifying an action for policy learning (e.g. NRPA).
+    ///
+    /// Defaults to a hash of the action itself; games can override this
+    /// to take the context of the move (e.g. the board position) into account.
+    fn action_code(&self, action: &A) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        action.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Prior score of an action in the current game state; higher is better.
+    ///
+    /// Used to order the expansion of tree nodes and, as a probability up to
+    /// normalization, to sample Gumbel root candidates; must not be negative.
+    /// Defaults to 1 for all actions.
+    fn action_prior(&self, _action: &A) -> f32 {
+        1.
+    }
+
+    /// Can moves be reverted with `unmake_move`?
+    ///
+    /// Games returning `true` are searched on a single mutable game state
+    /// instead of being cloned for every MCTS iteration and playout.
+    fn supports_unmake(&self) -> bool {
+        false
+    }
+
+    /// Revert `action`, which must have been the last move made.
+    ///
+    /// The search only calls this if `supports_unmake` returns `true`;
+    /// games that override one of the two must override the other.
+    fn unmake_move(&mut self, _action: &A) {
+        panic!("unmake_move called on a game whose supports_unmake() is false")
+    }
+
+    /// Number of players taking turns; 1 for puzzles such as 2048.
+    fn num_players(&self) -> usize {
+        1
+    }
+
+    /// The player to move, in `0..num_players()`.
+    fn current_player(&self) -> usize {
+        0
+    }
+
+    /// Reward for `player` when reaching the current game state.
+    ///
+    /// `reward` and `decided_reward` are the rewards of player 0; games with
+    /// several players override this. Defaults to `reward` for every player.
+    fn reward_for(&self, _player: usize) -> f32 {
+        self.reward()
+    }
 }
 
 /// A `GameAction` represents a move in a game.
@@ -36,28 +169,209 @@ pub trait GameAction: Debug+Clone+Copy+Eq+Hash {}
 
 /// Perform a random playout.
 ///
-/// Start with an initial game state and perform random actions from
-/// until a game-state is reached that does not have any `allowed_actions`.
-pub fn playout<G: Game<A>, A: GameAction>(initial: &G) -> G {
-    let mut game = initial.clone();
+/// Start with an initial game state and perform random actions
+/// until a game-state is reached that does not have any `allowed_actions`,
+/// whose outcome is already decided, or until `max_depth` random moves
+/// have been made.
+pub fn playout<G: Game<A>, A: GameAction, R: Rng>(initial: &G, max_depth: Option<usize>, rng: &mut R) -> Result<G, GameError> {
+    rollout(initial, max_depth, rng).map(|(game, _)| game)
+}
 
-    let mut potential_moves = game.allowed_actions();
-    while potential_moves.len() > 0 {
-        let action = choose_random(&potential_moves).clone();
-        game.make_move(&action);
-        potential_moves = game.allowed_actions();
-    }
-    game
+/// Perform a random playout and return the reward it achieved.
+///
+/// Playouts that are cut off by `max_depth` are scored with `Game::evaluate`.
+pub fn playout_reward<G: Game<A>, A: GameAction, R: Rng>(initial: &G, max_depth: Option<usize>, rng: &mut R) -> Result<f32, GameError> {
+    rollout(initial, max_depth, rng).map(|(_, reward)| reward)
 }
 
 /// Calculate the expected reward based on random playouts.
-pub fn expected_reward<G: Game<A>, A: GameAction>(game: &G, n_samples: usize) -> f32 {
+pub fn expected_reward<G: Game<A>, A: GameAction, R: Rng>(game: &G, n_samples: usize, max_depth: Option<usize>, rng: &mut R) -> Result<f32, GameError> {
     let mut score_sum: f32 = 0.0;
 
+    let mut game = game.clone();
     for _ in 0..n_samples {
-        score_sum += playout(game).reward();
+        score_sum += restoring_playout(&mut game, max_depth, rng, playout_value)?;
+    }
+    Ok(score_sum / (n_samples as f32))
+}
+
+/// Reward of a game state in which no further moves will be made.
+pub fn final_reward<G: Game<A>, A: GameAction>(game: &G) -> f32 {
+    game.decided_reward().unwrap_or_else(|| game.reward())
+}
+
+/// Rewards of every player in a game state in which no further moves will be made.
+///
+/// Single-player games are scored with `final_reward`, games with several
+/// players with `Game::reward_for` for every player. Playouts cut off
+/// before the end use `Game::evaluate_for` instead.
+pub fn final_rewards<G: Game<A>, A: GameAction>(game: &G) -> Vec<f32> {
+    let end = match game.decided_reward() {
+        Some(reward) => PlayoutEnd::Decided(reward),
+        None => PlayoutEnd::Final,
+    };
+    playout_rewards(game, end)
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Why a playout stopped.
+enum PlayoutEnd {
+    /// The outcome was decided with the given reward.
+    Decided(f32),
+    /// The maximum playout depth was reached.
+    CutOff,
+    /// No allowed actions were left.
+    Final,
+}
+
+/// Reward (of player 0) at the end of a playout.
+#[inline]
+fn playout_value<G: Game<A>, A: GameAction>(game: &G, end: PlayoutEnd) -> f32 {
+    match end {
+        PlayoutEnd::Decided(reward) => reward,
+        PlayoutEnd::CutOff if !game.is_terminal() => game.evaluate(),
+        _ => game.reward(),
+    }
+}
+
+/// Rewards of every player at the end of a playout; see `final_rewards`.
+fn playout_rewards<G: Game<A>, A: GameAction>(game: &G, end: PlayoutEnd) -> Vec<f32> {
+    match game.num_players() {
+        1 => vec![playout_value(game, end)],
+        players => match end {
+            PlayoutEnd::CutOff if !game.is_terminal() => (0..players).map(|player| game.evaluate_for(player)).collect(),
+            _ => (0..players).map(|player| game.reward_for(player)).collect(),
+        },
+    }
+}
+
+/// Perform a random playout; return the final game state and its reward.
+fn rollout<G: Game<A>, A: GameAction, R: Rng>(initial: &G, max_depth: Option<usize>, rng: &mut R) -> Result<(G, f32), GameError> {
+    let mut game = initial.clone();
+    let end = rollout_in_place(&mut game, max_depth, rng, None)?;
+    let reward = playout_value(&game, end);
+    Ok((game, reward))
+}
+
+/// Perform a random playout and `score` its final state, leaving `game` unchanged.
+///
+/// Games that support `unmake_move` are played on directly and restored
+/// afterwards; all others are cloned.
+fn restoring_playout<G, A, R, T, F>(game: &mut G, max_depth: Option<usize>, rng: &mut R, score: F) -> Result<T, GameError>
+    where G: Game<A>, A: GameAction, R: Rng, F: Fn(&G, PlayoutEnd) -> T
+{
+    if !game.supports_unmake() {
+        let mut game = game.clone();
+        let end = rollout_in_place(&mut game, max_depth, rng, None)?;
+        return Ok(score(&game, end));
+    }
+
+    let mut made = Vec::new();
+    let result = rollout_in_place(game, max_depth, rng, Some(&mut made))
+            .map(|end| score(game, end));
+    for action in made.iter().rev() {
+        game.unmake_move(action);
+    }
+    result
+}
+
+/// Perform random moves on `game` itself until the playout ends.
+///
+/// All moves made are recorded in `made` (if given) so they can be reverted.
+fn rollout_in_place<G: Game<A>, A: GameAction, R: Rng>(game: &mut G, max_depth: Option<usize>, rng: &mut R, mut made: Option<&mut Vec<A>>) -> Result<PlayoutEnd, GameError> {
+    let mut depth = 0;
+    loop {
+        if let Some(reward) = game.decided_reward() {
+            return Ok(PlayoutEnd::Decided(reward));
+        }
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return Ok(PlayoutEnd::CutOff);
+        }
+        let action = match game.random_action(rng) {
+            Some(action) => action,
+            None => return Ok(PlayoutEnd::Final),
+        };
+        game.try_make_move(&action)?;
+        if let Some(ref mut made) = made {
+            made.push(action);
+        }
+        depth += 1;
     }
-    (score_sum as f32) / (n_samples as f32)
+}
+
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+/// Selection and backup strategy used inside the tree.
+pub enum TreePolicy {
+    /// UCT1 selection with mean backup.
+    #[default]
+    Uct,
+    /// Single-player MCTS (Schadd et al., 2008).
+    ///
+    /// Adds the variance term `sqrt((sum(x^2) - n*mean^2 + d) / n)` to UCT1;
+    /// with `max_backup` nodes are valued by the best reward seen instead of
+    /// the mean reward.
+    SpMcts { d: f32, max_backup: bool },
+}
+
+impl TreePolicy {
+    /// Are nodes valued by the best reward seen rather than the mean?
+    pub fn value_is_max(&self) -> bool {
+        match *self {
+            TreePolicy::SpMcts { max_backup, .. } => max_backup,
+            TreePolicy::Uct => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+/// How the action at the root is chosen in every iteration.
+///
+/// The simple-regret strategies allocate the iterations of a search call
+/// among the root actions in rounds (see the `halving` module); interior
+/// nodes always use the `TreePolicy`. They take effect once all root
+/// actions have been expanded. `MCTS::best_action` then recommends the most
+/// visited action for Sequential Halving, and for the Gumbel variant the
+/// remaining candidate with the highest Gumbel score plus scaled value.
+pub enum RootPolicy {
+    /// Select root actions with the tree policy, like any other node.
+    #[default]
+    Tree,
+    /// Sequential Halving over all root actions.
+    SequentialHalving,
+    /// Gumbel variant of Sequential Halving: sample `m` root actions without
+    /// replacement according to `Game::action_prior` and rank them by
+    /// Gumbel noise, log-prior and the normalized value scaled by
+    /// `(c_visit + max visits) * c_scale`.
+    Gumbel { m: usize, c_visit: f32, c_scale: f32 },
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+/// Settings shared by all iterations of an MCTS search.
+pub struct SearchSettings {
+    /// Cut random playouts off after this many moves and use the static
+    /// evaluation of the game instead (`None` plays until the end).
+    pub max_playout_depth: Option<usize>,
+    /// Selection and backup strategy.
+    pub tree_policy: TreePolicy,
+    /// Order in which untried actions are added to the tree.
+    pub expansion_order: ExpansionOrder,
+    /// Selection strategy at the root.
+    pub root_policy: RootPolicy,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+/// Order in which the untried actions of a node are expanded.
+pub enum ExpansionOrder {
+    /// Uniformly random order.
+    #[default]
+    Random,
+    /// The order returned by `Game::fill_actions`.
+    InOrder,
+    /// Highest `Game::action_prior` first; ties in `fill_actions` order.
+    Prior,
 }
 
 
@@ -71,9 +385,12 @@ enum NodeState {
 #[derive(Debug)]
 pub struct TreeNode<A: GameAction> {
     action: Option<A>,                  // how did we get here
+    player: usize,                      // who chose the action (root: who is to move)
     children: Vec<TreeNode<A>>,         // next steps we investigated
+    untried: Option<Vec<A>>,            // actions still to expand, next one last (None: not visited yet)
     state: NodeState,                   // is this a leaf node? fully expanded?
-    n: f32, q: f32                      // statistics for this game state
+    n: f32, q: f32,                     // statistics for this game state
+    sq: f32, best: f32                  // sum of squared rewards; best reward seen
 }
 
 impl<A> TreeNode<A> where A: GameAction {
@@ -85,17 +402,101 @@ impl<A> TreeNode<A> where A: GameAction {
     pub fn new(action: Option<A>) -> TreeNode<A> {
         TreeNode::<A> {
             action: action,
+            player: 0,
             children: Vec::new(),
+            untried: None,
             state: NodeState::Expandable,
-            n: 0., q: 0. }
+            n: 0., q: 0.,
+            sq: 0., best: f32::NEG_INFINITY }
+    }
+
+    /// The action leading to this node (`None` for the root).
+    pub fn action(&self) -> Option<A> {
+        self.action
+    }
+
+    /// The player whose rewards this node collects: the player who chose
+    /// the action leading here, or for the root the player to move.
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    /// The children expanded so far, in order of expansion.
+    pub fn children(&self) -> &[TreeNode<A>] {
+        &self.children
+    }
+
+    /// Number of iterations through this node.
+    pub fn visits(&self) -> f32 {
+        self.n
+    }
+
+    /// Have all allowed actions been added as children?
+    pub fn is_fully_expanded(&self) -> bool {
+        matches!(self.state, NodeState::FullyExpanded)
+    }
+
+    /// Value of this node under the given tree policy.
+    ///
+    /// This is the mean reward, or the best reward seen when the policy
+    /// backs up the maximum, of the node's `player`.
+    pub fn value(&self, policy: &TreePolicy) -> f32 {
+        if policy.value_is_max() {
+            self.best
+        } else {
+            self.q / self.n
+        }
+    }
+
+    /// Add the reward of a single playout to the statistics of this node.
+    fn update(&mut self, delta: f32) {
+        self.n += 1.;
+        self.q += delta;
+        self.sq += delta * delta;
+        self.best = self.best.max(delta);
     }
 
     /// Gather some statistics about this subtree
+    ///
+    /// Depths are counted from this node, which has depth 0.
     pub fn tree_statistics(&self) -> TreeStatistics {
-        let child_stats = self.children.iter()
-                .map(|c| c.tree_statistics())
-                .collect::<Vec<_>>();
-        TreeStatistics::merge(child_stats)
+        let mut stats = TreeStatistics {
+            trees: 1,
+            min_depth: usize::MAX,
+            memory_bytes: mem::size_of::<TreeNode<A>>(),
+            .. TreeStatistics::default()
+        };
+        let mut visit_depth = 0.;
+        self.add_statistics(0, &mut stats, &mut visit_depth);
+        if stats.visits > 0. {
+            stats.average_depth = visit_depth / stats.visits;
+        }
+        stats
+    }
+
+    /// Add this node and its subtree at the given depth to `stats`.
+    fn add_statistics(&self, depth: usize, stats: &mut TreeStatistics, visit_depth: &mut f32) {
+        stats.nodes += 1;
+        stats.visits += self.n;
+        *visit_depth += self.n * depth as f32;
+        if stats.depth_histogram.len() <= depth {
+            stats.depth_histogram.push(0);
+        }
+        stats.depth_histogram[depth] += 1;
+        stats.max_depth = max(stats.max_depth, depth);
+        stats.memory_bytes += self.children.capacity() * mem::size_of::<TreeNode<A>>()
+                + self.untried.as_ref().map_or(0, |untried| untried.capacity()) * mem::size_of::<A>();
+
+        if self.children.is_empty() {
+            stats.leaf_nodes += 1;
+            stats.min_depth = min(stats.min_depth, depth);
+            if let NodeState::LeafNode = self.state {
+                stats.terminal_nodes += 1;
+            }
+        }
+        for child in &self.children {
+            child.add_statistics(depth + 1, stats, visit_depth);
+        }
     }
 
     /*
@@ -105,13 +506,17 @@ impl<A> TreeNode<A> where A: GameAction {
     }
     */
 
-    /// Find the best child accoring to UCT1
-    pub fn best_child(&mut self, c: f32) -> Option<&mut TreeNode<A>> {
+    /// Find the best child accoring to UCT1 (or SP-MCTS)
+    pub fn best_child(&mut self, c: f32, policy: &TreePolicy) -> Option<&mut TreeNode<A>> {
         let mut best_value :f32 = f32::NEG_INFINITY;
         let mut best_child :Option<&mut TreeNode<A>> = None;
 
         for child in &mut self.children {
-            let value = child.q / child.n + c*(2.*self.n.ln()/child.n).sqrt();
+            let mut value = child.value(policy) + c*(2.*self.n.ln()/child.n).sqrt();
+            if let TreePolicy::SpMcts { d, .. } = *policy {
+                let mean = child.q / child.n;
+                value += ((child.sq - child.n*mean*mean + d) / child.n).sqrt();
+            }
             if value > best_value {
                 best_value = value;
                 best_child = Some(child);
@@ -122,38 +527,47 @@ impl<A> TreeNode<A> where A: GameAction {
 
     /// Add a child to the current node with an previously unexplored action.
     ///
-    /// XXX Use HashSet? Use iterators? XXX
-    pub fn expand<G: Game<A>>(&mut self, game: &G) -> Option<&mut TreeNode<A>> {
+    /// The allowed actions are computed once, on the first visit, and
+    /// queued in the given expansion order.
+    pub fn expand<G: Game<A>, R: Rng>(&mut self, game: &G, order: ExpansionOrder, rng: &mut R) -> Option<&mut TreeNode<A>> {
 
         // What are our options given the current game state?
-        let allowed_actions = game.allowed_actions();
-        if allowed_actions.len() == 0 {
-            self.state = NodeState::LeafNode;
-            return None;
-        }
-
-        // Get a list with all the actions we tried alreday
-        let mut child_actions : Vec<A> = Vec::new();
-        for child in &self.children {
-                child_actions.push(child.action.expect("Child node without action"));
-        }
-
-        // Find untried actions
-        let mut candidate_actions = Vec::new();
-        for action in &allowed_actions {
-            if !child_actions.contains(action) {
-                candidate_actions.push(action);
+        if self.untried.is_none() {
+            let mut actions = Vec::new();
+            if game.decided_reward().is_none() {
+                game.fill_actions(&mut actions);
             }
+            match order {
+                ExpansionOrder::Random => rng.shuffle(&mut actions),
+                ExpansionOrder::InOrder => actions.reverse(),
+                ExpansionOrder::Prior => {
+                    actions.reverse();
+                    let mut priors = actions.iter()
+                            .map(|a| (game.action_prior(a), *a))
+                            .collect::<Vec<_>>();
+                    priors.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+                    actions = priors.into_iter().map(|(_, a)| a).collect();
+                },
+            }
+            self.untried = Some(actions);
         }
 
-        if candidate_actions.len() == 1 {
+        let untried = self.untried.as_mut().expect("Untried actions not initialized");
+        let action = match untried.pop() {
+            Some(action) => action,
+            None => {
+                self.state = NodeState::LeafNode;
+                return None;
+            }
+        };
+        if untried.is_empty() {
             self.state = NodeState::FullyExpanded;
+            *untried = Vec::new();
         }
 
-        // Select random actions
-        let action = *choose_random(&candidate_actions).clone();
-
-        self.children.push(TreeNode::new(Some(action)));
+        let mut child = TreeNode::new(Some(action));
+        child.player = game.current_player();
+        self.children.push(child);
         self.children.last_mut()
     }
 
@@ -162,34 +576,89 @@ impl<A> TreeNode<A> where A: GameAction {
     /// XXX A non-recursive implementation would probably be faster.
     /// XXX But how to keep &mut pointers to all our parents while
     /// XXX we fiddle with our leaf node?
-    pub fn iteration<G: Game<A>>(&mut self, game: &mut G, c: f32) -> f32 {
-        let delta = match self.state {
+    ///
+    /// Errors reported by the game abort the iteration without
+    /// updating any statistics.
+    ///
+    /// Games that support `unmake_move` are restored to their initial state
+    /// after a successful iteration; all others are left at the state where
+    /// the iteration left the tree.
+    ///
+    /// Returns the rewards of all players (see `final_rewards`); every node
+    /// on the path is updated with the reward of its own `player`.
+    pub fn iteration<G: Game<A>, R: Rng>(&mut self, game: &mut G, c: f32, settings: &SearchSettings, rng: &mut R) -> Result<Vec<f32>, GameError> {
+        if self.action.is_none() {
+            self.player = game.current_player();
+        }
+        let rewards = match self.state {
             NodeState::LeafNode => {
-                game.reward()
+                final_rewards(game)
             },
             NodeState::FullyExpanded => {
                 // Choose and recurse into child...
-                let child = self.best_child(c).unwrap();
-                game.make_move(&child.action.unwrap());
-                child.iteration(game, c)
+                let child = self.best_child(c, &settings.tree_policy).expect("Fully expanded node without children");
+                TreeNode::descend(child, game, c, settings, rng)?
             },
             NodeState::Expandable => {
-                let child = self.expand(game);
-                match child {
+                let expanded = match self.expand(game, settings.expansion_order, rng) {
                     Some(child) => {           // We expanded our current node...
-                        game.make_move(&child.action.unwrap());
-                        let delta = playout(game).reward();
-                        child.n += 1.;
-                        child.q += delta;
-                        delta
+                        let action = child.action.expect("Child node without action");
+                        let result = game.try_make_move(&action)
+                                .and_then(|_| restoring_playout(game, settings.max_playout_depth, rng, playout_rewards));
+                        if let Ok(ref rewards) = result {
+                            child.update(rewards[child.player]);
+                            if game.supports_unmake() {
+                                game.unmake_move(&action);
+                            }
+                        }
+                        Some(result)
                     },
-                    None => game.reward()      // Could not expand, current node is a leaf node!
+                    None => None               // Could not expand, current node is a leaf node!
+                };
+                match expanded {
+                    Some(Ok(rewards)) => rewards,
+                    Some(Err(err)) => {
+                        // Forget about the child we could not evaluate
+                        let child = self.children.pop().expect("Expanded node without children");
+                        if let Some(ref mut untried) = self.untried {
+                            untried.push(child.action.expect("Child node without action"));
+                        }
+                        self.state = NodeState::Expandable;
+                        return Err(err);
+                    },
+                    None => final_rewards(game)
                 }
             }
         };
-        self.n += 1.;
-        self.q += delta;
-        delta
+        self.update(rewards[self.player]);
+        Ok(rewards)
+    }
+
+    /// Perform an MCTS iteration through the child reached by `action`.
+    ///
+    /// Lets root policies choose the first action themselves; below the
+    /// child the tree policy takes over.
+    pub fn iteration_via<G: Game<A>, R: Rng>(&mut self, action: &A, game: &mut G, c: f32, settings: &SearchSettings, rng: &mut R) -> Result<Vec<f32>, GameError> {
+        if self.action.is_none() {
+            self.player = game.current_player();
+        }
+        let child = self.children.iter_mut()
+                .find(|child| child.action == Some(*action))
+                .expect("No child for the given action");
+        let rewards = TreeNode::descend(child, game, c, settings, rng)?;
+        self.update(rewards[self.player]);
+        Ok(rewards)
+    }
+
+    /// Play the action of `child` and recurse into it.
+    fn descend<G: Game<A>, R: Rng>(child: &mut TreeNode<A>, game: &mut G, c: f32, settings: &SearchSettings, rng: &mut R) -> Result<Vec<f32>, GameError> {
+        let action = child.action.expect("Child node without action");
+        game.try_make_move(&action)?;
+        let rewards = child.iteration(game, c, settings, rng)?;
+        if game.supports_unmake() {
+            game.unmake_move(&action);
+        }
+        Ok(rewards)
     }
 }
 
@@ -218,77 +687,234 @@ impl<A: GameAction> fmt::Display for TreeNode<A> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone, Default)]
 /// Store and process some simple statistical information about NodeTrees.
+///
+/// Statistics of an ensamble aggregate those of its trees: counts are
+/// summed, depths refer to the individual trees (the root has depth 0).
 pub struct TreeStatistics {
-    nodes: i32,
-    min_depth: i32,
-    max_depth: i32,
+    /// Number of trees aggregated.
+    pub trees: usize,
+    /// Number of nodes, including the roots.
+    pub nodes: usize,
+    /// Nodes without children.
+    pub leaf_nodes: usize,
+    /// Leaf nodes known to have no allowed actions.
+    ///
+    /// A node is only known to be terminal after its second visit.
+    pub terminal_nodes: usize,
+    /// Depth of the shallowest leaf node.
+    pub min_depth: usize,
+    /// Depth of the deepest node.
+    pub max_depth: usize,
+    /// Mean node depth, weighted by the number of visits of every node.
+    pub average_depth: f32,
+    /// Number of nodes at every depth.
+    pub depth_histogram: Vec<usize>,
+    /// Visits summed over all nodes.
+    pub visits: f32,
+    /// Approximate heap and stack memory used by the nodes (in bytes).
+    pub memory_bytes: usize,
+    /// MCTS iterations per tree for the current game state.
+    pub iterations: usize,
+    /// Iterations per tree and second during the last search call.
+    pub iterations_per_s: f32,
 }
 
 impl TreeStatistics {
-    fn merge(child_stats: Vec<TreeStatistics>) -> TreeStatistics {
-        if child_stats.len() == 0 {
-            TreeStatistics {
-                nodes: 1,
-                min_depth: 0,
-                max_depth: 0,
+    /// Aggregate the statistics of several trees.
+    pub fn merge(tree_stats: &[TreeStatistics]) -> TreeStatistics {
+        let mut merged = TreeStatistics {
+            min_depth: tree_stats.iter()
+                    .map(|stats| stats.min_depth)
+                    .min()
+                    .unwrap_or(0),
+            .. TreeStatistics::default()
+        };
+        let mut visit_depth = 0.;
+        for stats in tree_stats {
+            merged.trees += stats.trees;
+            merged.nodes += stats.nodes;
+            merged.leaf_nodes += stats.leaf_nodes;
+            merged.terminal_nodes += stats.terminal_nodes;
+            merged.max_depth = max(merged.max_depth, stats.max_depth);
+            merged.visits += stats.visits;
+            merged.memory_bytes += stats.memory_bytes;
+            visit_depth += stats.average_depth * stats.visits;
+
+            if merged.depth_histogram.len() < stats.depth_histogram.len() {
+                merged.depth_histogram.resize(stats.depth_histogram.len(), 0);
             }
-        } else {
-            TreeStatistics {
-                nodes: child_stats.iter()
-                        .fold(0, |sum, child| sum + child.nodes),
-                min_depth: 1 + child_stats.iter()
-                        .fold(i32::MAX, |depth, child| min(depth, child.min_depth)),
-                max_depth: 1 + child_stats.iter()
-                        .fold(0, |depth, child| max(depth, child.max_depth)),
+            for (sum, count) in merged.depth_histogram.iter_mut().zip(&stats.depth_histogram) {
+                *sum += *count;
             }
         }
+        if merged.visits > 0. {
+            merged.average_depth = visit_depth / merged.visits;
+        }
+        merged
+    }
+
+    /// Effective branching factor: average number of children of inner nodes.
+    pub fn branching_factor(&self) -> f32 {
+        let inner_nodes = self.nodes - self.leaf_nodes;
+        if inner_nodes == 0 {
+            0.
+        } else {
+            (self.nodes - self.trees) as f32 / inner_nodes as f32
+        }
     }
 }
 //////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+/// Statistics of an action at the root, merged over the ensamble.
+pub struct ActionStatistics<A: GameAction> {
+    pub action: A,
+    /// Number of visits summed over all trees.
+    pub n: f32,
+    /// Mean reward; or, if the tree policy backs up the maximum, the best
+    /// reward seen averaged over the trees.
+    pub value: f32,
+}
+
+/// Choose the root action of the next iteration according to the root
+/// policy, or `None` to leave it to the tree policy.
+///
+/// Schedules that have used up their budget are replaced by one with twice
+/// the budget, so that searches without a known budget stay anytime.
+fn root_action<G: Game<A>, A: GameAction, R: Rng>(root: &TreeNode<A>, game: &G, schedule: &mut Option<SequentialHalving<A>>, settings: &SearchSettings, budget: usize, rng: &mut R) -> Option<A> {
+    if settings.root_policy == RootPolicy::Tree || !root.is_fully_expanded() {
+        return None;
+    }
+
+    let budget = match *schedule {
+        Some(ref schedule) if !schedule.is_exhausted() => None,
+        Some(ref schedule) => Some(2 * schedule.budget()),
+        None => Some(budget),
+    };
+    if let Some(budget) = budget {
+        *schedule = Some(match settings.root_policy {
+            RootPolicy::Gumbel { m, .. } => SequentialHalving::gumbel(root, game, m, budget, rng),
+            _ => SequentialHalving::new(root, budget),
+        });
+    }
+    schedule.as_mut().map(|schedule| schedule.next_action(root, settings))
+}
+
 /// Represents an ensamble of MCTS trees.
 ///
 /// For many applications we need to work with ensambles because we use
 /// determinization.
+///
+/// All random decisions of the search are drawn from random number
+/// generators owned by the solver -- one independent stream per tree --
+/// so that a given seed always produces the same trees and actions.
 pub struct MCTS<G: Game<A>, A: GameAction> {
     roots: Vec<TreeNode<A>>,
     games: Vec<G>,
+    rngs: Vec<XorShiftRng>,
     iterations_per_s: f32,
+    settings: SearchSettings,
+    observers: Vec<Box<dyn SearchObserver<A>>>,
+    report_interval: usize,         // iterations between progress events
+    iterations: usize,              // iterations per tree for the current game state
+    search_started: (f64, usize),   // time and iterations when the current search call started
+    reported_best: Option<A>,       // best action reported to the observers
+    schedules: Vec<Option<SequentialHalving<A>>>,   // root schedule per tree
+    root_budget: usize,             // iterations planned for the current search call
 }
 
 impl<G: Game<A>, A: GameAction> MCTS<G, A> {
 
     /// Create a new MCTS solver.
     pub fn new(game: &G, ensamble_size: usize) -> MCTS<G, A> {
+        MCTS::with_seed(game, ensamble_size, 0)
+    }
+
+    /// Create a new MCTS solver whose random decisions derive from `seed`.
+    pub fn with_seed(game: &G, ensamble_size: usize, seed: u32) -> MCTS<G, A> {
         let mut roots = Vec::new();
         let mut games = Vec::new();
         for i in 0..ensamble_size {
             let mut game = game.clone();
-            game.set_rng_seed(i as u32);
+            let observer = game.current_player();
+            game.determinize(observer, i as u32);
             games.push(game);
             roots.push(TreeNode::new(None));
         }
-        MCTS {
+        let mut mcts = MCTS {
             roots: roots,
             games: games,
-            iterations_per_s: 1.
-        }
+            rngs: Vec::new(),
+            iterations_per_s: 1.,
+            settings: SearchSettings::default(),
+            observers: Vec::new(),
+            report_interval: 100,
+            iterations: 0,
+            search_started: (0., 0),
+            reported_best: None,
+            schedules: Vec::new(),
+            root_budget: 0,
+        };
+        mcts.set_seed(seed);
+        mcts
     }
 
-    /// Return basic statistical data about the current MCTS tree.
-    ///
-    /// XXX Note: The current implementation considers the ensemble
-    /// to be a tree layer. In other words tree depth and number of
-    /// nodes are all one too large.
+    /// Reseed the random number generators of all trees.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rngs = (0..self.games.len())
+                .map(|i| seeded_rng(seed, i as u32))
+                .collect();
+        self.start_schedules(0);
+    }
+
+    /// Return the settings used for MCTS iterations.
+    pub fn settings(&self) -> &SearchSettings {
+        &self.settings
+    }
+
+    /// Change the settings used for subsequent MCTS iterations.
+    pub fn set_settings(&mut self, settings: SearchSettings) {
+        self.settings = settings;
+    }
+
+    /// Register an observer receiving `SearchEvent`s during the search.
+    pub fn add_observer<O: SearchObserver<A> + 'static>(&mut self, observer: O) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Emit a progress event every `iterations` MCTS iterations (default: 100).
+    pub fn set_report_interval(&mut self, iterations: usize) {
+        self.report_interval = max(iterations, 1);
+    }
+
+    /// Number of MCTS iterations per tree performed for the current game state.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Return basic statistical data about the current MCTS trees,
+    /// aggregated over the ensamble.
     pub fn tree_statistics(&self) -> TreeStatistics {
-        let child_stats = self.roots.iter()
-                    .map(|c| c.tree_statistics())
-                    .collect::<Vec<_>>();
-        TreeStatistics::merge(child_stats)
+        TreeStatistics {
+            iterations: self.iterations,
+            iterations_per_s: self.iterations_per_s,
+            .. TreeStatistics::merge(&self.ensemble_statistics())
+        }
     }
+
+    /// Return statistical data about every tree of the ensamble.
+    pub fn ensemble_statistics(&self) -> Vec<TreeStatistics> {
+        self.roots.iter()
+                .map(|root| TreeStatistics {
+                    iterations: self.iterations,
+                    iterations_per_s: self.iterations_per_s,
+                    .. root.tree_statistics()
+                })
+                .collect()
+    }
+
     /// Set a new game state for this solver.
     pub fn advance_game(&mut self, game: &G) {
         let ensamble_size = self.games.len();
@@ -297,39 +923,101 @@ impl<G: Game<A>, A: GameAction> MCTS<G, A> {
         let mut games = Vec::new();
         for i in 0..ensamble_size {
             let mut game = game.clone();
-            game.set_rng_seed(i as u32);
+            let observer = game.current_player();
+            game.determinize(observer, i as u32);
             games.push(game);
             roots.push(TreeNode::new(None));
         }
         self.games = games;
         self.roots = roots;
+        self.iterations = 0;
+        self.reported_best = None;
+        self.start_schedules(0);
     }
 
     /// Perform n_samples MCTS iterations.
-    pub fn search(&mut self, n_samples: usize, c: f32) {
+    ///
+    /// Stops at the first error reported by the game.
+    pub fn search(&mut self, n_samples: usize, c: f32) -> Result<(), GameError> {
+        self.start_reporting();
+        self.start_schedules(n_samples);
+        self.search_iterations(n_samples, c)?;
+        self.report_finished();
+        Ok(())
+    }
+
+    /// Perform n_samples MCTS iterations, reporting progress to the observers.
+    fn search_iterations(&mut self, n_samples: usize, c: f32) -> Result<(), GameError> {
+        let mut remaining = n_samples;
+        while remaining > 0 {
+            // Stop at every multiple of the report interval if anybody listens
+            let batch = if self.observers.is_empty() {
+                remaining
+            } else {
+                min(remaining, self.report_interval - self.iterations % self.report_interval)
+            };
+            self.search_batch(batch, c)?;
+            self.iterations += batch;
+            remaining -= batch;
+
+            if !self.observers.is_empty() && self.iterations.is_multiple_of(self.report_interval) {
+                self.report_progress();
+            }
+        }
+        Ok(())
+    }
+
+    /// Perform n_samples MCTS iterations on every tree of the ensamble.
+    fn search_batch(&mut self, n_samples: usize, c: f32) -> Result<(), GameError> {
         let ensamble_size = self.games.len();
+        let settings = self.settings;
+        let budget = self.root_budget.saturating_sub(self.iterations - self.search_started.1);
 
         // Iterate over ensamble and perform MCTS iterations
         for e in 0..ensamble_size {
             let game = &self.games[e];
             let root = &mut self.roots[e];
+            let rng = &mut self.rngs[e];
+            let schedule = &mut self.schedules[e];
 
-            // Perform MCTS iterations
+            // Perform MCTS iterations -- on a single game state if we
+            // can unmake moves, on a fresh clone per iteration otherwise.
+            let mut this_game = game.clone();
             for _ in 0..n_samples {
-                let mut this_game = game.clone();
-                root.iteration(&mut this_game, c);
+                let mut cloned;
+                let iteration_game = if this_game.supports_unmake() {
+                    &mut this_game
+                } else {
+                    cloned = game.clone();
+                    &mut cloned
+                };
+                match root_action(root, game, schedule, &settings, budget, rng) {
+                    Some(action) => root.iteration_via(&action, iteration_game, c, &settings, rng)?,
+                    None => root.iteration(iteration_game, c, &settings, rng)?,
+                };
             }
         }
+        Ok(())
+    }
+
+    /// Discard the root schedules and plan `budget` iterations for the next ones.
+    fn start_schedules(&mut self, budget: usize) {
+        self.schedules = self.games.iter().map(|_| None).collect();
+        self.root_budget = budget;
     }
 
     /// Perform MCTS iterations for the given time budget (in s).
-    pub fn search_time(&mut self, budget_seconds: f32, c: f32) {
+    ///
+    /// Stops at the first error reported by the game.
+    pub fn search_time(&mut self, budget_seconds: f32, c: f32) -> Result<(), GameError> {
         let mut samples_total = 0;
         let t0 = time::now();
+        self.start_reporting();
+        self.start_schedules((self.iterations_per_s*budget_seconds) as usize);
 
         let mut n_samples = (self.iterations_per_s*budget_seconds).max(10.).min(100.) as usize;
         while n_samples >= 5 {
-            self.search(n_samples, c);
+            self.search_iterations(n_samples, c)?;
             samples_total += n_samples;
 
             let time_spend = (time::now()-t0).num_milliseconds() as f32 / 1000.;
@@ -339,47 +1027,190 @@ impl<G: Game<A>, A: GameAction> MCTS<G, A> {
             n_samples = (self.iterations_per_s*time_left).max(0.).min(100.) as usize;
 
         }
+        self.report_finished();
+        Ok(())
     }
 
-    /// Return the best action found so far by averaging over the ensamble.
-    pub fn best_action(&self) -> Option<A> {
-        let ensamble_size = self.games.len();
+    /// Perform MCTS iterations until `stop` is set.
+    ///
+    /// `on_batch` is called after every few iterations, e.g. to publish the
+    /// current best action to another thread.
+    pub fn search_until<F: FnMut(&Self)>(&mut self, c: f32, stop: &AtomicBool, mut on_batch: F) -> Result<(), GameError> {
+        self.start_reporting();
+        self.start_schedules(0);
+        while !stop.load(AtomicOrdering::SeqCst) {
+            self.search_iterations(10, c)?;
+            on_batch(self);
+        }
+        self.report_finished();
+        Ok(())
+    }
 
-        // Merge ensamble results
-        let mut n_values = HashMap::<A, f32>::new();
-        let mut q_values = HashMap::<A, f32>::new();
+    fn start_reporting(&mut self) {
+        self.search_started = (time::precise_time_s(), self.iterations);
+    }
 
-        for e in 0..ensamble_size {
-            let root = &self.roots[e];
+    fn elapsed_s(&self) -> f32 {
+        (time::precise_time_s() - self.search_started.0) as f32
+    }
 
+    fn notify(&mut self, event: SearchEvent<A>) {
+        for observer in &mut self.observers {
+            observer.on_event(&event);
+        }
+    }
+
+    /// Send a progress snapshot and report changes of the best action.
+    fn report_progress(&mut self) {
+        let elapsed_s = self.elapsed_s();
+        let iterations = self.iterations;
+        let event = SearchEvent::Progress {
+            iterations,
+            elapsed_s,
+            iterations_per_s: (iterations - self.search_started.1) as f32 / elapsed_s,
+            root: self.root_statistics(),
+        };
+        self.notify(event);
+        self.report_best_action();
+    }
+
+    fn report_best_action(&mut self) {
+        if let Some(best) = self.best_statistics() {
+            if Some(best.action) != self.reported_best {
+                let event = SearchEvent::BestActionChanged {
+                    iterations: self.iterations,
+                    previous: self.reported_best,
+                    best: best.action,
+                    value: best.value,
+                };
+                self.reported_best = Some(best.action);
+                self.notify(event);
+            }
+        }
+    }
+
+    fn report_finished(&mut self) {
+        let elapsed_s = self.elapsed_s();
+        if elapsed_s > 0. {
+            self.iterations_per_s = (self.iterations - self.search_started.1) as f32 / elapsed_s;
+        }
+        if self.observers.is_empty() {
+            return;
+        }
+        self.report_best_action();
+        let event = SearchEvent::Finished {
+            iterations: self.iterations,
+            elapsed_s,
+            best: self.reported_best,
+        };
+        self.notify(event);
+    }
+
+    /// Return the statistics of all actions tried at the root, merged over the ensamble.
+    ///
+    /// Actions are listed in the order they were first expanded.
+    pub fn root_statistics(&self) -> Vec<ActionStatistics<A>> {
+        let max_backup = self.settings.tree_policy.value_is_max();
+
+        // Merge ensamble results: (n, q, sum of best rewards, number of trees)
+        let mut index = HashMap::<A, usize>::new();
+        let mut merged = Vec::<(A, f32, f32, f32, f32)>::new();
+
+        for root in &self.roots {
             for child in &root.children {
-                let action = child.action.unwrap();
+                let action = child.action.expect("Child node without action");
+                let i = *index.entry(action).or_insert_with(|| {
+                    merged.push((action, 0., 0., 0., 0.));
+                    merged.len() - 1
+                });
+
+                let stats = &mut merged[i];
+                stats.1 += child.n;
+                stats.2 += child.q;
+                stats.3 += child.best;
+                stats.4 += 1.;
+            }
+        }
 
-                let n = n_values.entry(action).or_insert(0.);
-                let q = q_values.entry(action).or_insert(0.);
+        merged.into_iter()
+            .map(|(action, n, q, best, trees)| ActionStatistics {
+                action,
+                n,
+                value: if max_backup { best / trees } else { q / n },
+            })
+            .collect()
+    }
 
-                *n += child.n;
-                *q += child.q;
+    /// The action recommended by the Gumbel schedules of most trees (see
+    /// `SequentialHalving::recommendation`); ties go to the higher value.
+    fn gumbel_recommendation(&self) -> Option<A> {
+        let mut votes = HashMap::<A, usize>::new();
+        for (root, schedule) in self.roots.iter().zip(&self.schedules) {
+            if let Some(action) = schedule.as_ref().and_then(|schedule| schedule.recommendation(root, &self.settings)) {
+                *votes.entry(action).or_insert(0) += 1;
             }
         }
+        let mut best: Option<(A, usize, f32)> = None;
+        for stats in self.root_statistics() {
+            let count = votes.get(&stats.action).cloned().unwrap_or(0);
+            if count > 0 && best.is_none_or(|(_, best_count, best_value)| {
+                count > best_count || (count == best_count && stats.value > best_value)
+            }) {
+                best = Some((stats.action, count, stats.value));
+            }
+        }
+        best.map(|(action, _, _)| action)
+    }
 
-        // Find best action
-        let mut best_action: Option<A> = None;
-        let mut best_value: f32 = f32::NEG_INFINITY;
-        for (action, n) in &n_values {
-            let q = q_values.get(action).unwrap();
-            let value = q / n;
-            if value > best_value {
-                best_action = Some(*action);
-                best_value = value;
+    /// Statistics of the action with the highest value (or, for the
+    /// simple-regret root policies, the recommended one).
+    fn best_statistics(&self) -> Option<ActionStatistics<A>> {
+        if let RootPolicy::Gumbel { .. } = self.settings.root_policy {
+            if let Some(action) = self.gumbel_recommendation() {
+                return self.root_statistics().into_iter().find(|stats| stats.action == action);
+            }
+        }
+        let by_visits = self.settings.root_policy != RootPolicy::Tree;
+        let mut best: Option<ActionStatistics<A>> = None;
+        for stats in self.root_statistics() {
+            if best.as_ref().is_none_or(|best| {
+                if by_visits && stats.n != best.n {
+                    stats.n > best.n
+                } else {
+                    stats.value > best.value
+                }
+            }) {
+                best = Some(stats);
             }
         }
+        best
+    }
 
-        best_action
+    /// Return the best action found so far by averaging over the ensamble.
+    ///
+    /// When the tree policy backs up the maximum, actions are ranked by the
+    /// best reward seen, averaged over the trees of the ensemble. With
+    /// Sequential Halving the most visited action is returned; with the
+    /// Gumbel root policy the one recommended by most trees.
+    pub fn best_action(&self) -> Option<A> {
+        self.best_statistics().map(|stats| stats.action)
     }
 }
 
 
+impl<G: Game<A> + Debug, A: GameAction> Debug for MCTS<G, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MCTS")
+            .field("roots", &self.roots)
+            .field("games", &self.games)
+            .field("iterations_per_s", &self.iterations_per_s)
+            .field("settings", &self.settings)
+            .field("observers", &self.observers.len())
+            .field("iterations", &self.iterations)
+            .finish()
+    }
+}
+
 impl<G: Game<A>, A: GameAction> fmt::Display for MCTS<G, A> {
 
     /// Output a nicely indented tree
@@ -403,7 +1234,16 @@ mod tests {
     use test::Bencher;
 
     use mcts::*;
-    use minigame::MiniGame;
+    use minigame;
+    use minigame::{MiniGame, Tweaked};
+    use tictactoe::TicTacToe;
+    use twofortyeight::TwoFortyEight;
+    use std::mem;
+    use std::sync::{Arc, Mutex};
+
+    use utils::seeded_rng;
+    use error::GameError;
+    use observer::SearchEvent;
 
     /*
     // Are the given
@@ -424,23 +1264,54 @@ mod tests {
     #[test]
     fn test_playout() {
         let game = MiniGame::new();
-        let game = playout(&game);
+        let game = playout(&game, None, &mut seeded_rng(0, 0)).unwrap();
         println!("Final: {:?}", game);
     }
 
+    #[test]
+    fn test_playout_max_depth() {
+        let game = MiniGame::new();
+        let mut rng = seeded_rng(0, 0);
+
+        assert_eq!(playout_reward(&game, Some(0), &mut rng), Ok(0.));
+        assert_eq!(expected_reward(&game, 10, Some(1), &mut rng), Ok(0.));
+    }
+
     #[test]
     fn test_expand() {
         let game = MiniGame::new();
         let mut node = TreeNode::new(None);
+        let mut rng = seeded_rng(0, 0);
+        let order = ExpansionOrder::Random;
 
-        node.expand(&game);
-        node.expand(&game);
+        node.expand(&game, order, &mut rng);
+        node.expand(&game, order, &mut rng);
         {
-            let v = node.expand(&game).unwrap();
-            v.expand(&game);
+            let v = node.expand(&game, order, &mut rng).unwrap();
+            v.expand(&game, order, &mut rng);
         }
 
         println!("After some expands:\n{}", node);
+        assert!(node.expand(&game, order, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_expansion_order() {
+        let game = MiniGame::new();
+        let mut rng = seeded_rng(0, 0);
+        let expand_all = |order, rng: &mut _| {
+            let mut node = TreeNode::new(None);
+            while node.expand(&game, order, rng).is_some() {}
+            node.children.iter().map(|c| c.action.unwrap()).collect::<Vec<_>>()
+        };
+
+        let actions = game.allowed_actions();
+        assert_eq!(expand_all(ExpansionOrder::InOrder, &mut rng), actions);
+
+        let mut random = expand_all(ExpansionOrder::Random, &mut rng);
+        assert_eq!(random.len(), actions.len());
+        random.retain(|a| actions.contains(a));
+        assert_eq!(random.len(), actions.len());
     }
 
     #[test]
@@ -448,11 +1319,25 @@ mod tests {
         let game = MiniGame::new();
         let mut mcts = MCTS::new(&game, 2);
 
-        mcts.search(50, 1.);
+        mcts.search(50, 1.).unwrap();
 
         let stats = mcts.tree_statistics();
-
-        println!("{:?}", stats);
+        assert_eq!(stats.trees, 2);
+        assert_eq!(stats.iterations, 50);
+        assert_eq!(stats.visits, mcts.ensemble_statistics().iter().map(|s| s.visits).sum::<f32>());
+        assert_eq!(stats.depth_histogram[0], 2);
+        assert_eq!(stats.depth_histogram.iter().sum::<usize>(), stats.nodes);
+        assert_eq!(stats.depth_histogram.len(), stats.max_depth + 1);
+        assert!(stats.min_depth >= 1 && stats.min_depth <= stats.max_depth);
+        assert!(stats.terminal_nodes > 0 && stats.terminal_nodes <= stats.leaf_nodes);
+        assert!(stats.average_depth > 0. && stats.average_depth < stats.max_depth as f32);
+        assert!(stats.branching_factor() > 1. && stats.branching_factor() <= 3.);
+        assert!(stats.memory_bytes > stats.nodes * mem::size_of::<TreeNode<minigame::Action>>());
+
+        // A single node
+        let stats = TreeNode::<minigame::Action>::new(None).tree_statistics();
+        assert_eq!((stats.nodes, stats.leaf_nodes, stats.min_depth, stats.max_depth), (1, 1, 0, 0));
+        assert_eq!(stats.branching_factor(), 0.);
     }
 
     /*
@@ -475,11 +1360,193 @@ mod tests {
         let game = MiniGame::new();
         let mut mcts = MCTS::new(&game, 2);
 
-        mcts.search(50, 1.);
+        mcts.search(50, 1.).unwrap();
 
         println!("Search result: {:?}", mcts.best_action());
     }
 
+    #[test]
+    fn test_search_reproducible() {
+        let game = TwoFortyEight::new();
+        let trees = |seed| {
+            let mut mcts = MCTS::with_seed(&game, 2, seed);
+            mcts.search(50, 1.).unwrap();
+            let trees = mcts.roots.iter()
+                    .map(|root| format!("{}", root))
+                    .collect::<Vec<_>>();
+            (trees, mcts.best_action())
+        };
+
+        assert_eq!(trees(42), trees(42));
+        assert!(trees(42).0 != trees(43).0);
+    }
+
+    #[test]
+    fn test_search_sp_mcts() {
+        let game = MiniGame::new();
+        let mut mcts = MCTS::new(&game, 2);
+        mcts.set_settings(SearchSettings {
+            tree_policy: TreePolicy::SpMcts { d: 1., max_backup: true },
+            .. SearchSettings::default()
+        });
+
+        mcts.search(200, 1.).unwrap();
+
+        // Some playout reaches the winning sum
+        for root in &mcts.roots {
+            assert_eq!(root.best, 1.);
+            assert!(root.sq <= root.n);
+        }
+        mcts.best_action().expect("should give some action");
+    }
+
+    #[test]
+    fn test_search_root_policies() {
+        let game = MiniGame::new();
+        for &root_policy in &[RootPolicy::SequentialHalving, RootPolicy::Gumbel { m: 2, c_visit: 50., c_scale: 1. }] {
+            let mut mcts = MCTS::new(&game, 2);
+            mcts.set_settings(SearchSettings { root_policy, .. SearchSettings::default() });
+
+            mcts.search(100, 1.).unwrap();
+
+            for root in &mcts.roots {
+                assert_eq!(root.n, 100.);
+                assert_eq!(root.children.len(), 3);
+            }
+            let best = mcts.best_action().unwrap();
+            if root_policy == RootPolicy::SequentialHalving {
+                // The recommended action is the most visited one
+                let stats = mcts.root_statistics();
+                let max_visits = stats.iter().map(|s| s.n).fold(0., f32::max);
+                assert!(stats.iter().any(|s| s.action == best && s.n == max_visits));
+            } else {
+                // ...or the one recommended by the schedules
+                let recommended = mcts.roots.iter().zip(&mcts.schedules)
+                        .filter_map(|(root, schedule)| schedule.as_ref().unwrap().recommendation(root, &mcts.settings))
+                        .collect::<Vec<_>>();
+                assert!(recommended.contains(&best));
+            }
+
+            // Searches without a known budget keep going
+            mcts.search_time(0.01, 1.).unwrap();
+            assert!(mcts.iterations() > 100);
+        }
+    }
+
+    #[test]
+    fn test_best_child_sp_mcts() {
+        let actions = MiniGame::new().allowed_actions();
+        let mut node = TreeNode::new(None);
+        for (&action, &rewards) in actions.iter().zip(&[[0., 0.], [-1., 1.]]) {
+            let mut child = TreeNode::new(Some(action));
+            for &r in &rewards {
+                child.update(r);
+                node.update(r);
+            }
+            node.children.push(child);
+        }
+        // Same mean; UCT1 keeps the first child, SP-MCTS prefers the high variance one
+        let uct = node.best_child(1., &TreePolicy::Uct).unwrap().sq;
+        assert_eq!(uct, 0.);
+        let sp = node.best_child(1., &TreePolicy::SpMcts { d: 0., max_backup: false }).unwrap().sq;
+        assert_eq!(sp, 2.);
+    }
+
+    #[test]
+    fn test_search_unmake() {
+        let game = MiniGame::new();
+        let mut unmaking = MCTS::with_seed(&game, 2, 7);
+        let mut cloning = MCTS::with_seed(&Tweaked { no_unmake: true, .. Tweaked::new(game.clone()) }, 2, 7);
+
+        unmaking.search(100, 1.).unwrap();
+        cloning.search(100, 1.).unwrap();
+
+        for (a, b) in unmaking.roots.iter().zip(&cloning.roots) {
+            assert_eq!(format!("{}", a), format!("{}", b));
+        }
+        assert_eq!(unmaking.games[0].reward(), 0.);
+    }
+
+    #[test]
+    #[should_panic(expected = "supports_unmake")]
+    fn test_unmake_not_supported() {
+        let mut game = TwoFortyEight::new();
+        let action = game.allowed_actions()[0];
+        game.make_move(&action);
+        game.unmake_move(&action);
+    }
+
+    #[test]
+    fn test_cut_off_two_players() {
+        // TicTacToe with a static evaluation favouring x
+        let game = Tweaked { evaluation: Some(0.5), .. Tweaked::new(TicTacToe::new()) };
+        assert_eq!((game.evaluate_for(0), game.evaluate_for(1)), (0.5, -0.5));
+
+        let root_values = |max_playout_depth| {
+            let mut mcts = MCTS::with_seed(&game, 1, 0);
+            mcts.set_settings(SearchSettings { max_playout_depth, .. SearchSettings::default() });
+            mcts.search(9, 1.).unwrap();
+            mcts.root_statistics().iter().map(|stats| stats.value).collect::<Vec<_>>()
+        };
+        // Every first move of x is expanded once and scored by the evaluation...
+        assert_eq!(root_values(Some(0)), vec![0.5; 9]);
+        // ...instead of the outcome of a random game
+        let played = root_values(None);
+        assert_eq!(played.len(), 9);
+        assert!(played.iter().all(|&value| value == 1. || value == 0. || value == -1.));
+    }
+
+    #[test]
+    fn test_search_error() {
+        // A broken game that reports moves as legal but refuses to make them
+        let game = Tweaked { reject_moves: true, .. Tweaked::new(MiniGame::new()) };
+        let mut mcts = MCTS::new(&game, 2);
+
+        assert!(matches!(mcts.search(10, 1.), Err(GameError::IllegalMove(_))));
+        assert_eq!(mcts.best_action(), None);
+        assert!(playout(&game, None, &mut seeded_rng(0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_observers() {
+        let game = MiniGame::new();
+        let mut mcts = MCTS::new(&game, 2);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        {
+            let events = events.clone();
+            mcts.add_observer(move |event: &SearchEvent<minigame::Action>| {
+                events.lock().unwrap().push(event.clone());
+            });
+        }
+        mcts.set_report_interval(10);
+
+        mcts.search(50, 1.).unwrap();
+
+        let events = events.lock().unwrap();
+        let progress = events.iter()
+                .filter_map(|e| match *e {
+                    SearchEvent::Progress { iterations, ref root, .. } => Some((iterations, root.len())),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(progress, vec![(10, 3), (20, 3), (30, 3), (40, 3), (50, 3)]);
+
+        match events[1] {
+            SearchEvent::BestActionChanged { iterations, previous, .. } => {
+                assert_eq!(iterations, 10);
+                assert_eq!(previous, None);
+            },
+            ref e => panic!("Unexpected event {:?}", e),
+        }
+        match *events.last().unwrap() {
+            SearchEvent::Finished { iterations, best, .. } => {
+                assert_eq!(iterations, 50);
+                assert_eq!(best, mcts.best_action());
+            },
+            ref e => panic!("Unexpected event {:?}", e),
+        }
+    }
+
     #[test]
     fn test_search_time() {
         let game = MiniGame::new();
@@ -489,7 +1556,7 @@ mod tests {
         let budget_seconds = 0.5;
 
         let t0 = time::now();
-        mcts.search_time(budget_seconds, 1.);
+        mcts.search_time(budget_seconds, 1.).unwrap();
 
         let time_spent = (time::now() - t0).num_milliseconds();
 
@@ -503,13 +1570,15 @@ mod tests {
     #[bench]
     fn bench_playout(b: &mut Bencher) {
         let game = MiniGame::new();
-        b.iter(|| playout(&game))
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap())
     }
 
     #[bench]
     fn bench_expected(b: &mut Bencher) {
         let game = MiniGame::new();
-        b.iter(|| expected_reward(&game, 100))
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| expected_reward(&game, 100, None, &mut rng).unwrap())
     }
 
     #[bench]
@@ -517,7 +1586,7 @@ mod tests {
         let game = MiniGame::new();
         let mut mcts = MCTS::new(&game, 1);
 
-        b.iter(|| mcts.search(10, 1.0))
+        b.iter(|| mcts.search(10, 1.0).unwrap())
     }
 
 }
@@ -12,6 +12,10 @@
 //!
 
 use std::fmt;
+use rand::Rng;
+
+#[cfg(test)]
+use error::GameError;
 use mcts::{GameAction, Game};
 
 const WINNING_SUM :u32 = 11;
@@ -45,13 +49,29 @@ impl Game<Action> for MiniGame {
     /// Return a list with all allowed actions given the current game state.
     fn allowed_actions(&self) -> Vec<Action> {
         let mut moves = Vec::new();
+        self.fill_actions(&mut moves);
+        moves
+    }
 
+    fn fill_actions(&self, moves: &mut Vec<Action>) {
+        moves.clear();
         if self.sum < WINNING_SUM {
             for add in DRAW_MIN..DRAW_MAX {
-                moves.push(Action{add: add});
+                moves.push(Action{add});
             }
         }
-        moves
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.sum >= WINNING_SUM
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        if self.is_terminal() {
+            None
+        } else {
+            Some(Action{add: rng.gen_range(DRAW_MIN, DRAW_MAX)})
+        }
     }
 
     /// Change the current game state according to the given action.
@@ -69,4 +89,124 @@ impl Game<Action> for MiniGame {
 
     /// Derterminize the game
     fn set_rng_seed(&mut self, _: u32) { }
+
+    /// Prefer moves that do not overshoot.
+    fn action_prior(&self, a_move: &Action) -> f32 {
+        if self.sum + a_move.add <= WINNING_SUM { 1. } else { 0. }
+    }
+
+    fn supports_unmake(&self) -> bool {
+        true
+    }
+
+    fn unmake_move(&mut self, a_move: &Action) {
+        self.sum -= a_move.add;
+    }
+
+    /// The game is lost as soon as every possible move overshoots.
+    fn decided_reward(&self) -> Option<f32> {
+        if self.sum < WINNING_SUM && self.sum + DRAW_MIN > WINNING_SUM {
+            Some(-1.)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone)]
+/// Any game with parts of its behaviour replaced, as a fixture for tests
+/// of the search algorithms. Everything else is forwarded to `game`.
+pub struct Tweaked<G> {
+    pub game: G,
+    /// Reject every move in `try_make_move`.
+    pub reject_moves: bool,
+    /// Report `supports_unmake` as false, so that the game is cloned.
+    pub no_unmake: bool,
+    /// Reward of every player, replacing the one of `game`.
+    pub reward: Option<f32>,
+    /// Static evaluation, replacing the one of `game`.
+    pub evaluation: Option<f32>,
+}
+
+#[cfg(test)]
+impl<G> Tweaked<G> {
+    /// Wrap `game` without changing anything yet.
+    pub fn new(game: G) -> Tweaked<G> {
+        Tweaked { game, reject_moves: false, no_unmake: false, reward: None, evaluation: None }
+    }
+}
+
+#[cfg(test)]
+impl<G: Game<A>, A: GameAction> Game<A> for Tweaked<G> {
+    fn allowed_actions(&self) -> Vec<A> { self.game.allowed_actions() }
+    fn fill_actions(&self, actions: &mut Vec<A>) { self.game.fill_actions(actions) }
+    fn is_terminal(&self) -> bool { self.game.is_terminal() }
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<A> { self.game.random_action(rng) }
+    fn make_move(&mut self, action: &A) { self.try_make_move(action).expect("Illegal move") }
+    fn try_make_move(&mut self, action: &A) -> Result<(), GameError> {
+        if self.reject_moves {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        self.game.try_make_move(action)
+    }
+    fn reward(&self) -> f32 { self.reward.unwrap_or_else(|| self.game.reward()) }
+    fn set_rng_seed(&mut self, seed: u32) { self.game.set_rng_seed(seed) }
+    fn determinize(&mut self, observer: usize, seed: u32) { self.game.determinize(observer, seed) }
+    fn evaluate(&self) -> f32 { self.evaluation.unwrap_or_else(|| self.game.evaluate()) }
+    fn decided_reward(&self) -> Option<f32> {
+        self.game.decided_reward().map(|reward| self.reward.unwrap_or(reward))
+    }
+    fn action_code(&self, action: &A) -> u64 { self.game.action_code(action) }
+    fn action_prior(&self, action: &A) -> f32 { self.game.action_prior(action) }
+    fn supports_unmake(&self) -> bool { !self.no_unmake && self.game.supports_unmake() }
+    fn unmake_move(&mut self, action: &A) { self.game.unmake_move(action) }
+    fn num_players(&self) -> usize { self.game.num_players() }
+    fn current_player(&self) -> usize { self.game.current_player() }
+    fn reward_for(&self, player: usize) -> f32 { self.reward.unwrap_or_else(|| self.game.reward_for(player)) }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use mcts::*;
+    use minigame::*;
+    use utils::seeded_rng;
+
+    #[test]
+    fn test_decided_reward() {
+        assert_eq!(MiniGame{sum: 8}.decided_reward(), None);
+        assert_eq!(MiniGame{sum: 9}.decided_reward(), Some(-1.));
+        assert_eq!(MiniGame{sum: 11}.decided_reward(), None);
+
+        // Playouts stop as soon as the game is decided
+        let game = playout(&MiniGame{sum: 10}, None, &mut seeded_rng(0, 0)).unwrap();
+        assert_eq!(game.sum, 10);
+    }
+
+    #[test]
+    fn test_prior_expansion() {
+        let game = MiniGame{sum: 7};
+        let mut node = TreeNode::new(None);
+        let mut rng = seeded_rng(0, 0);
+
+        // The overshooting move is expanded last
+        let mut expanded = Vec::new();
+        while let Some(child) = node.expand(&game, ExpansionOrder::Prior, &mut rng) {
+            expanded.push(game.action_prior(&child.action().unwrap()));
+        }
+        assert_eq!(expanded, vec![1., 1., 0.]);
+    }
+
+    #[test]
+    fn test_unmake_move() {
+        let mut game = MiniGame::new();
+        let action = Action{add: 4};
+
+        game.make_move(&action);
+        assert_eq!(game.sum, 4);
+        game.unmake_move(&action);
+        assert_eq!(game.sum, 0);
+    }
 }
@@ -0,0 +1,310 @@
+//!
+//! Nested Monte Carlo Search (NMCS) and Nested Rollout Policy Adaptation (NRPA).
+//!
+//! Both algorithms are designed for single-player optimization problems
+//! such as 2048 or puzzles: instead of estimating the value of the first
+//! move they keep track of the best complete sequence of actions found so far.
+//!
+//! [1] T. Cazenave: Nested Monte-Carlo Search (IJCAI 2009)
+//! [2] C. D. Rosin: Nested Rollout Policy Adaptation for Monte Carlo Tree Search (IJCAI 2011)
+//!
+
+use std::f32;
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{Game, GameAction, final_reward};
+
+#[derive(Debug, Clone)]
+/// A sequence of actions together with the reward it achieves.
+pub struct Sequence<A: GameAction> {
+    pub score: f32,
+    pub actions: Vec<A>,
+}
+
+impl<A: GameAction> Sequence<A> {
+    fn worst() -> Sequence<A> {
+        Sequence {
+            score: f32::NEG_INFINITY,
+            actions: Vec::new(),
+        }
+    }
+}
+
+/// Return the allowed actions, or an empty list when the game is over.
+fn legal_actions<G: Game<A>, A: GameAction>(game: &G) -> Vec<A> {
+    if game.decided_reward().is_some() {
+        Vec::new()
+    } else {
+        game.allowed_actions()
+    }
+}
+
+/// Perform a random playout and record the actions taken.
+fn sample<G: Game<A>, A: GameAction, R: Rng>(initial: &G, rng: &mut R) -> Result<Sequence<A>, GameError> {
+    let mut game = initial.clone();
+    let mut actions = Vec::new();
+
+    while game.decided_reward().is_none() {
+        let action = match game.random_action(rng) {
+            Some(action) => action,
+            None => break,
+        };
+        game.try_make_move(&action)?;
+        actions.push(action);
+    }
+    Ok(Sequence {
+        score: final_reward(&game),
+        actions,
+    })
+}
+
+/// Nested Monte Carlo Search.
+///
+/// At `level` 0 this is a single random playout. At higher levels every
+/// allowed action is evaluated with a search of `level - 1` and the game
+/// proceeds along the best sequence found so far. Returns the best
+/// sequence of actions starting at `game`.
+pub fn nmcs<G: Game<A>, A: GameAction, R: Rng>(game: &G, level: usize, rng: &mut R) -> Result<Sequence<A>, GameError> {
+    if level == 0 {
+        return sample(game, rng);
+    }
+
+    let mut game = game.clone();
+    let mut best = Sequence::worst();
+    let mut played = Vec::new();
+
+    loop {
+        let potential_moves = legal_actions(&game);
+        if potential_moves.is_empty() {
+            break;
+        }
+
+        for action in &potential_moves {
+            let mut next_game = game.clone();
+            next_game.try_make_move(action)?;
+
+            let result = nmcs(&next_game, level - 1, rng)?;
+            if result.score > best.score {
+                let mut actions = played.clone();
+                actions.push(*action);
+                actions.extend(result.actions);
+                best = Sequence {
+                    score: result.score,
+                    actions,
+                };
+            }
+        }
+
+        // Follow the best sequence for one step; if no score beat the
+        // worst one (e.g. all rewards are NaN or -inf) take the first move.
+        let action = match best.actions.get(played.len()) {
+            Some(&action) => action,
+            None => {
+                best.actions.push(potential_moves[0]);
+                potential_moves[0]
+            },
+        };
+        game.try_make_move(&action)?;
+        played.push(action);
+    }
+
+    if best.actions.len() == played.len() {
+        best.score = final_reward(&game);
+    }
+    Ok(best)
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Default)]
+/// A playout policy: one weight per action code (see `Game::action_code`).
+pub struct Policy {
+    weights: HashMap<u64, f32>,
+}
+
+impl Policy {
+    /// Create a uniform policy.
+    pub fn new() -> Policy {
+        Policy { weights: HashMap::new() }
+    }
+
+    /// Return the weight for the given action code.
+    pub fn weight(&self, code: u64) -> f32 {
+        *self.weights.get(&code).unwrap_or(&0.)
+    }
+
+    /// Perform a playout sampling actions proportional to exp(weight).
+    pub fn playout<G: Game<A>, A: GameAction, R: Rng>(&self, initial: &G, rng: &mut R) -> Result<Sequence<A>, GameError> {
+        let mut game = initial.clone();
+        let mut actions = Vec::new();
+
+        let mut potential_moves = Vec::new();
+        let mut probs = Vec::new();
+        loop {
+            if game.decided_reward().is_some() {
+                break;
+            }
+            game.fill_actions(&mut potential_moves);
+            if potential_moves.is_empty() {
+                break;
+            }
+            probs.clear();
+            probs.extend(potential_moves.iter()
+                    .map(|a| self.weight(game.action_code(a)).exp()));
+
+            let mut r = rng.gen::<f32>() * probs.iter().sum::<f32>();
+            let mut idx = 0;
+            while idx + 1 < probs.len() && r >= probs[idx] {
+                r -= probs[idx];
+                idx += 1;
+            }
+
+            let action = potential_moves[idx];
+            game.try_make_move(&action)?;
+            actions.push(action);
+        }
+        Ok(Sequence {
+            score: final_reward(&game),
+            actions,
+        })
+    }
+
+    /// Shift the policy towards the given sequence of actions.
+    ///
+    /// Every action of the sequence gains `alpha`; all alternatives lose
+    /// weight proportional to their current probability.
+    pub fn adapt<G: Game<A>, A: GameAction>(&self, initial: &G, actions: &[A], alpha: f32) -> Result<Policy, GameError> {
+        let mut adapted = self.clone();
+        let mut game = initial.clone();
+
+        for action in actions {
+            let potential_moves = game.allowed_actions();
+            let codes = potential_moves.iter()
+                    .map(|a| game.action_code(a))
+                    .collect::<Vec<_>>();
+            let z = codes.iter()
+                    .map(|&code| self.weight(code).exp())
+                    .sum::<f32>();
+
+            *adapted.weights.entry(game.action_code(action)).or_insert(0.) += alpha;
+            for &code in &codes {
+                *adapted.weights.entry(code).or_insert(0.) -= alpha * self.weight(code).exp() / z;
+            }
+            game.try_make_move(action)?;
+        }
+        Ok(adapted)
+    }
+}
+
+/// Nested Rollout Policy Adaptation.
+///
+/// Performs `iterations` searches of `level - 1` per level and adapts the
+/// playout policy towards the best sequence found with learning rate `alpha`.
+/// Returns the best sequence of actions starting at `game`.
+pub fn nrpa<G: Game<A>, A: GameAction, R: Rng>(game: &G, level: usize, iterations: usize, alpha: f32, rng: &mut R) -> Result<Sequence<A>, GameError> {
+    nrpa_level(game, level, iterations, alpha, Policy::new(), rng)
+}
+
+fn nrpa_level<G: Game<A>, A: GameAction, R: Rng>(game: &G, level: usize, iterations: usize, alpha: f32, policy: Policy, rng: &mut R) -> Result<Sequence<A>, GameError> {
+    if level == 0 {
+        return policy.playout(game, rng);
+    }
+
+    let mut policy = policy;
+    let mut best = Sequence::worst();
+    for _ in 0..iterations {
+        let result = nrpa_level(game, level - 1, iterations, alpha, policy.clone(), rng)?;
+        if result.score >= best.score {
+            best = result;
+        }
+        policy = policy.adapt(game, &best.actions, alpha)?;
+    }
+    Ok(best)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use test::Bencher;
+
+    use mcts::*;
+    use nested::*;
+    use minigame::{MiniGame, Tweaked};
+    use twofortyeight::TwoFortyEight;
+    use utils::seeded_rng;
+
+    /// Replay the given sequence and return the final reward.
+    fn replay<G: Game<A>, A: GameAction>(game: &G, actions: &[A]) -> f32 {
+        let mut game = game.clone();
+        for action in actions {
+            game.make_move(action);
+        }
+        game.reward()
+    }
+
+    #[test]
+    fn test_nmcs() {
+        let game = MiniGame::new();
+        let best = nmcs(&game, 2, &mut seeded_rng(0, 0)).unwrap();
+
+        assert_eq!(best.score, 1.);
+        assert_eq!(replay(&game, &best.actions), best.score);
+    }
+
+    #[test]
+    fn test_nmcs_twofortyeight() {
+        let game = TwoFortyEight::new();
+        let best = nmcs(&game, 1, &mut seeded_rng(0, 0)).unwrap();
+
+        assert!(best.score > 0.);
+        assert_eq!(replay(&game, &best.actions), best.score);
+    }
+
+    #[test]
+    fn test_nmcs_without_improvement() {
+        // A reward no sequence can improve on
+        let game = Tweaked { reward: Some(f32::NEG_INFINITY), .. Tweaked::new(MiniGame::new()) };
+        let best = nmcs(&game, 2, &mut seeded_rng(0, 0)).unwrap();
+
+        assert_eq!(best.score, f32::NEG_INFINITY);
+        assert!(!best.actions.is_empty());
+        assert!(replay(&game.game, &best.actions).is_finite());
+    }
+
+    #[test]
+    fn test_nrpa() {
+        let game = MiniGame::new();
+        let best = nrpa(&game, 2, 10, 1., &mut seeded_rng(0, 0)).unwrap();
+
+        assert_eq!(best.score, 1.);
+        assert_eq!(replay(&game, &best.actions), best.score);
+    }
+
+    #[test]
+    fn test_policy_adapt() {
+        let game = MiniGame::new();
+        let actions = game.allowed_actions();
+
+        let policy = Policy::new().adapt(&game, &actions[..1], 1.).unwrap();
+        assert!(policy.weight(game.action_code(&actions[0])) > 0.);
+        assert!(policy.weight(game.action_code(&actions[1])) < 0.);
+    }
+
+    #[bench]
+    fn bench_nmcs(b: &mut Bencher) {
+        let game = MiniGame::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| nmcs(&game, 1, &mut rng).unwrap())
+    }
+
+    #[bench]
+    fn bench_nrpa(b: &mut Bencher) {
+        let game = MiniGame::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| nrpa(&game, 1, 10, 1., &mut rng).unwrap())
+    }
+}
@@ -0,0 +1,358 @@
+//!
+//! Impartial games on heaps of tokens: Nim and subtraction games.
+//!
+//! Two players take turns removing tokens from one heap; in Nim any
+//! positive number may be taken, in a subtraction game only the amounts in
+//! the subtraction set. Under normal play the player who cannot move loses
+//! and gets a reward of -1, the winner 1.
+//!
+//! By the Sprague-Grundy theorem every position is equivalent to a single
+//! Nim heap whose size, the Grundy value, is the XOR of the values of the
+//! heaps. The player to move wins exactly if it is not zero, and the
+//! winning moves are those that make it zero [1]. This makes the optimal
+//! moves known for any position, so the search can be checked against them.
+//!
+//! [1] E. R. Berlekamp, J. H. Conway, R. K. Guy: Winning Ways for your
+//!     Mathematical Plays (1982)
+//!
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Action {
+    pub heap: u32,
+    pub take: u32,
+}
+impl GameAction for Action {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// How many tokens may be taken from a heap.
+pub enum Rule {
+    /// Any positive number.
+    Nim,
+    /// One of the given amounts.
+    Subtraction(Vec<u32>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Nim {
+    heaps: Vec<u32>,
+    rule: Rule,
+    to_move: u8,
+}
+
+impl Nim {
+    /// Nim with the given heap sizes.
+    pub fn new(heaps: &[u32]) -> Nim {
+        Nim {
+            heaps: heaps.to_vec(),
+            rule: Rule::Nim,
+            to_move: 0,
+        }
+    }
+
+    /// The subtraction game with the given heap sizes and subtraction set.
+    pub fn subtraction(heaps: &[u32], set: &[u32]) -> Result<Nim, GameError> {
+        if set.is_empty() || set.contains(&0) {
+            return Err(GameError::Other(format!("invalid subtraction set {:?}", set)));
+        }
+        let mut set = set.to_vec();
+        set.sort();
+        set.dedup();
+        Ok(Nim {
+            heaps: heaps.to_vec(),
+            rule: Rule::Subtraction(set),
+            to_move: 0,
+        })
+    }
+
+    pub fn heaps(&self) -> &[u32] {
+        &self.heaps
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn allows(&self, heap: u32, take: u32) -> bool {
+        take >= 1 && take <= heap && match self.rule {
+            Rule::Nim => true,
+            Rule::Subtraction(ref set) => set.contains(&take),
+        }
+    }
+
+    /// Grundy values of single heaps of size 0 to `max`.
+    fn heap_values(&self, max: u32) -> Vec<u32> {
+        match self.rule {
+            Rule::Nim => (0..=max).collect(),
+            Rule::Subtraction(ref set) => {
+                let mut values = Vec::with_capacity(max as usize + 1);
+                for n in 0..=max {
+                    let options = set.iter()
+                            .filter(|&&s| s <= n)
+                            .map(|&s| values[(n - s) as usize])
+                            .collect::<Vec<u32>>();
+                    let mex = (0..).find(|g| !options.contains(g)).unwrap();
+                    values.push(mex);
+                }
+                values
+            },
+        }
+    }
+
+    /// Grundy value of the position; 0 if the player to move loses.
+    pub fn grundy_value(&self) -> u32 {
+        let max = self.heaps.iter().cloned().max().unwrap_or(0);
+        let values = self.heap_values(max);
+        self.heaps.iter().fold(0, |g, &heap| g ^ values[heap as usize])
+    }
+
+    /// The moves that win with perfect play; empty in a lost position.
+    pub fn winning_actions(&self) -> Vec<Action> {
+        self.allowed_actions().into_iter()
+            .filter(|action| {
+                let mut next = self.clone();
+                next.make_move(action);
+                next.grundy_value() == 0
+            })
+            .collect()
+    }
+}
+
+impl Game<Action> for Nim {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        match self.rule {
+            Rule::Nim => for (i, &heap) in self.heaps.iter().enumerate() {
+                actions.extend((1..=heap).map(|take| Action { heap: i as u32, take }));
+            },
+            Rule::Subtraction(ref set) => for (i, &heap) in self.heaps.iter().enumerate() {
+                actions.extend(set.iter()
+                        .take_while(|&&take| take <= heap)
+                        .map(|&take| Action { heap: i as u32, take }));
+            },
+        }
+    }
+
+    /// Uniform over all allowed actions, counted heap by heap instead of
+    /// listing them.
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        let count = |heap: u32| match self.rule {
+            Rule::Nim => heap as usize,
+            Rule::Subtraction(ref set) => set.iter().take_while(|&&take| take <= heap).count(),
+        };
+        let total = self.heaps.iter().map(|&heap| count(heap)).sum::<usize>();
+        if total == 0 {
+            return None;
+        }
+        let mut k = rng.gen_range(0, total);
+        for (i, &heap) in self.heaps.iter().enumerate() {
+            let n = count(heap);
+            if k < n {
+                let take = match self.rule {
+                    Rule::Nim => k as u32 + 1,
+                    Rule::Subtraction(ref set) => set[k],
+                };
+                return Some(Action { heap: i as u32, take });
+            }
+            k -= n;
+        }
+        unreachable!()
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        let heap = action.heap as usize;
+        if heap >= self.heaps.len() || !self.allows(self.heaps[heap], action.take) {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        self.heaps[heap] -= action.take;
+        self.to_move = 1 - self.to_move;
+        Ok(())
+    }
+
+    /// Reward of the first player.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn supports_unmake(&self) -> bool {
+        true
+    }
+
+    fn unmake_move(&mut self, action: &Action) {
+        self.heaps[action.heap as usize] += action.take;
+        self.to_move = 1 - self.to_move;
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.to_move as usize
+    }
+
+    /// -1 for the player who cannot move, 1 for the other; 0 before the end.
+    fn reward_for(&self, player: usize) -> f32 {
+        if !self.is_terminal() {
+            0.
+        } else if player == self.current_player() {
+            -1.
+        } else {
+            1.
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::Rng;
+
+    use mcts::*;
+    use nim::*;
+    use utils::seeded_rng;
+
+    fn take(heap: u32, take: u32) -> Action {
+        Action { heap, take }
+    }
+
+    fn mcts_action(game: &Nim, iterations: usize, seed: u32) -> Action {
+        let mut mcts = MCTS::with_seed(game, 1, seed);
+        mcts.search(iterations, 1.).unwrap();
+        mcts.best_action().unwrap()
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut game = Nim::new(&[1, 2]);
+        assert_eq!(game.allowed_actions(), vec![take(0, 1), take(1, 1), take(1, 2)]);
+        assert!(game.try_make_move(&take(0, 2)).is_err());
+        assert!(game.try_make_move(&take(2, 1)).is_err());
+        assert!(game.try_make_move(&take(1, 0)).is_err());
+
+        game.make_move(&take(1, 2));
+        game.make_move(&take(0, 1));
+        assert!(game.is_terminal());
+        assert_eq!(game.current_player(), 0);
+        assert_eq!((game.reward_for(0), game.reward_for(1)), (-1., 1.));
+        assert_eq!(game.decided_reward(), Some(-1.));
+        assert_eq!(game.random_action(&mut seeded_rng(0, 0)), None);
+
+        game.unmake_move(&take(0, 1));
+        assert_eq!(game.heaps(), &[1, 0]);
+        assert_eq!(game.current_player(), 1);
+        assert_eq!(game.reward_for(0), 0.);
+
+        let game = Nim::subtraction(&[5], &[3, 1, 3]).unwrap();
+        assert_eq!(game.rule(), &Rule::Subtraction(vec![1, 3]));
+        assert_eq!(game.allowed_actions(), vec![take(0, 1), take(0, 3)]);
+        assert!(game.clone().try_make_move(&take(0, 2)).is_err());
+        assert!(Nim::subtraction(&[5], &[0, 1]).is_err());
+        assert!(Nim::subtraction(&[5], &[]).is_err());
+
+        // More heaps than fit in a byte
+        let mut game = Nim::new(&[1; 300]);
+        assert_eq!(game.allowed_actions()[299], take(299, 1));
+        game.make_move(&take(299, 1));
+        assert_eq!(game.heaps()[299], 0);
+    }
+
+    #[test]
+    fn test_random_action() {
+        let mut rng = seeded_rng(0, 0);
+        for game in &[Nim::new(&[0, 3, 1]), Nim::subtraction(&[2, 0, 5], &[1, 3]).unwrap()] {
+            let mut counts = HashMap::new();
+            for _ in 0..1000 {
+                *counts.entry(game.random_action(&mut rng).unwrap()).or_insert(0) += 1;
+            }
+            let mut seen = counts.keys().cloned().collect::<Vec<_>>();
+            seen.sort_by_key(|action| (action.heap, action.take));
+            assert_eq!(seen, game.allowed_actions());
+            assert!(counts.values().all(|&n| n > 150), "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_grundy_values() {
+        assert_eq!(Nim::new(&[3, 4, 5]).grundy_value(), 2);
+        assert_eq!(Nim::new(&[1, 2, 3]).grundy_value(), 0);
+        assert_eq!(Nim::new(&[]).grundy_value(), 0);
+
+        // Taking 1 to 3 tokens: the value is the heap size modulo 4
+        for n in 0..20 {
+            assert_eq!(Nim::subtraction(&[n], &[1, 2, 3]).unwrap().grundy_value(), n % 4);
+        }
+        // Taking 2 or 5 tokens: period 7 with values 0 0 1 1 0 2 1
+        let values = (0..14).map(|n| Nim::subtraction(&[n], &[2, 5]).unwrap().grundy_value()).collect::<Vec<_>>();
+        assert_eq!(values, vec![0, 0, 1, 1, 0, 2, 1, 0, 0, 1, 1, 0, 2, 1]);
+
+        assert_eq!(Nim::new(&[3, 4, 5]).winning_actions(), vec![take(0, 2)]);
+        assert!(Nim::new(&[1, 2, 3]).winning_actions().is_empty());
+    }
+
+    #[test]
+    fn test_mcts_finds_winning_moves() {
+        assert_eq!(mcts_action(&Nim::new(&[1, 2, 4]), 1000, 0), take(2, 1));
+        // Only one of twelve moves wins; random playouts need many iterations to see it
+        assert_eq!(mcts_action(&Nim::new(&[3, 4, 5]), 50000, 0), take(0, 2));
+        assert_eq!(mcts_action(&Nim::subtraction(&[10], &[1, 2, 3]).unwrap(), 2000, 0), take(0, 2));
+
+        // Random winning positions
+        let mut rng = seeded_rng(0, 0);
+        let mut tested = 0;
+        while tested < 5 {
+            let heaps = (0..3).map(|_| rng.gen_range(0, 5)).collect::<Vec<u32>>();
+            let game = Nim::subtraction(&heaps, &[1, 2, 4]).unwrap();
+            let winning = game.winning_actions();
+            if winning.is_empty() {
+                continue;
+            }
+            let action = mcts_action(&game, 3000, tested);
+            assert!(winning.contains(&action), "{:?}: {:?} not in {:?}", heaps, action, winning);
+            tested += 1;
+        }
+    }
+
+    #[test]
+    fn test_optimal_play_wins() {
+        // Whoever moves first from a winning position wins against random replies
+        let mut rng = seeded_rng(1, 0);
+        let mut game = Nim::new(&[2, 5, 6]);
+        while !game.is_terminal() {
+            let action = if game.current_player() == 0 {
+                game.winning_actions()[0]
+            } else {
+                game.random_action(&mut rng).unwrap()
+            };
+            game.make_move(&action);
+        }
+        assert_eq!(game.reward(), 1.);
+    }
+}
@@ -0,0 +1,53 @@
+//!
+//! Observers receiving progress events from a running MCTS search.
+//!
+//! Register observers with `MCTS::add_observer`. Events are only assembled
+//! when at least one observer is registered, and progress snapshots are
+//! taken every `MCTS::set_report_interval` iterations, so observers are
+//! cheap enough to leave enabled.
+//!
+
+use mcts::{GameAction, ActionStatistics};
+
+#[derive(Debug, Clone)]
+/// Events emitted during `MCTS::search` and `MCTS::search_time`.
+///
+/// `iterations` counts the MCTS iterations per tree since the solver was
+/// created or last advanced; `elapsed_s` the time since the current call
+/// to `search` or `search_time` started.
+pub enum SearchEvent<A: GameAction> {
+    /// Periodic snapshot of the merged root statistics.
+    Progress {
+        iterations: usize,
+        elapsed_s: f32,
+        iterations_per_s: f32,
+        root: Vec<ActionStatistics<A>>,
+    },
+    /// The action `MCTS::best_action` would return has changed.
+    BestActionChanged {
+        iterations: usize,
+        previous: Option<A>,
+        best: A,
+        value: f32,
+    },
+    /// A call to `search` or `search_time` finished.
+    Finished {
+        iterations: usize,
+        elapsed_s: f32,
+        best: Option<A>,
+    },
+}
+
+/// Receives `SearchEvent`s from an `MCTS` solver.
+///
+/// Observers must be `Send` so that solvers can search on background
+/// threads. Closures taking a `&SearchEvent` are observers, too.
+pub trait SearchObserver<A: GameAction>: Send {
+    fn on_event(&mut self, event: &SearchEvent<A>);
+}
+
+impl<A: GameAction, F: FnMut(&SearchEvent<A>) + Send> SearchObserver<A> for F {
+    fn on_event(&mut self, event: &SearchEvent<A>) {
+        self(event)
+    }
+}
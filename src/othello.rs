@@ -0,0 +1,455 @@
+//!
+//! Implementation of Othello (Reversi) on bitboards.
+//!
+//! Black (`x`, player 0, moves first) and white (`o`, player 1) take turns
+//! placing a disc so that it encloses a line of opponent discs, which are
+//! then flipped. A player without such a move has to pass; the game ends
+//! when neither player can move. The final reward of a player is the disc
+//! difference divided by 64, so it lies in [-1, 1] and is positive for the
+//! winner. Empty squares are not counted.
+//!
+//! Squares are numbered row by row from `a1` (0) in the top left corner to
+//! `h8` (63) in the bottom right.
+//!
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = 0x8080_8080_8080_8080;
+
+/// Move every disc one step in direction `dir` (0..8), dropping those that
+/// leave the board.
+#[inline]
+fn shift(discs: u64, dir: usize) -> u64 {
+    match dir {
+        0 => (discs << 1) & !FILE_A,        // east
+        1 => (discs >> 1) & !FILE_H,        // west
+        2 => discs << 8,                    // south
+        3 => discs >> 8,                    // north
+        4 => (discs << 9) & !FILE_A,        // south-east
+        5 => (discs << 7) & !FILE_H,        // south-west
+        6 => (discs >> 7) & !FILE_A,        // north-east
+        _ => (discs >> 9) & !FILE_H,        // north-west
+    }
+}
+
+/// Squares where `own` can place a disc that flips discs of `opp`.
+fn legal_moves(own: u64, opp: u64) -> u64 {
+    let empty = !(own | opp);
+    let mut moves = 0;
+    for dir in 0..8 {
+        let mut line = shift(own, dir) & opp;
+        for _ in 0..5 {
+            line |= shift(line, dir) & opp;
+        }
+        moves |= shift(line, dir) & empty;
+    }
+    moves
+}
+
+/// Discs of `opp` flipped when `own` places a disc on `square`.
+fn flipped(own: u64, opp: u64, square: u64) -> u64 {
+    let mut flips = 0;
+    for dir in 0..8 {
+        let mut line = 0;
+        let mut next = shift(square, dir);
+        while next & opp != 0 {
+            line |= next;
+            next = shift(next, dir);
+        }
+        if next & own != 0 {
+            flips |= line;
+        }
+    }
+    flips
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Action {
+    /// Place a disc on a square (0..64).
+    Place(u8),
+    /// Only allowed if the player to move cannot place a disc.
+    Pass,
+}
+impl GameAction for Action {}
+
+impl Action {
+    /// Place a disc in the given row and column (both 0..8).
+    pub fn at(row: usize, col: usize) -> Action {
+        Action::Place((8 * row + col) as u8)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::Place(square) => write!(f, "{}{}", (b'a' + square % 8) as char, square / 8 + 1),
+            Action::Pass => write!(f, "pass"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = GameError;
+
+    /// Parse squares like `"d3"` or `"pass"`.
+    fn from_str(s: &str) -> Result<Action, GameError> {
+        if s == "pass" {
+            return Ok(Action::Pass);
+        }
+        let bytes = s.as_bytes();
+        match bytes {
+            [col @ b'a'..=b'h', row @ b'1'..=b'8'] => Ok(Action::at((row - b'1') as usize, (col - b'a') as usize)),
+            _ => Err(GameError::Other(format!("not a square: {:?}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Othello {
+    discs: [u64; 2],                    // discs of black and white
+    to_move: u8,
+}
+
+impl Othello {
+    /// The standard starting position with black to move.
+    pub fn new() -> Othello {
+        Othello {
+            discs: [(1 << 28) | (1 << 35), (1 << 27) | (1 << 36)],
+            to_move: 0,
+        }
+    }
+
+    /// A position given as 64 squares, row by row (`x` black, `o` white,
+    /// `.` empty; whitespace and `/` are ignored), with `to_move` to play.
+    pub fn from_board(board: &str, to_move: usize) -> Result<Othello, GameError> {
+        let mut discs = [0; 2];
+        let mut square = 0;
+        for c in board.chars().filter(|c| !(c.is_whitespace() || *c == '/')) {
+            if square >= 64 {
+                return Err(GameError::Other(format!("too many squares in {:?}", board)));
+            }
+            match c {
+                'x' | 'X' => discs[0] |= 1 << square,
+                'o' | 'O' => discs[1] |= 1 << square,
+                '.' | '-' => {},
+                _ => return Err(GameError::Other(format!("unexpected {:?} in {:?}", c, board))),
+            }
+            square += 1;
+        }
+        if square != 64 {
+            return Err(GameError::Other(format!("expected 64 squares in {:?}", board)));
+        }
+        if to_move > 1 {
+            return Err(GameError::Other(format!("no player {}", to_move)));
+        }
+        Ok(Othello { discs, to_move: to_move as u8 })
+    }
+
+    /// Number of discs of `player`.
+    pub fn disc_count(&self, player: usize) -> u32 {
+        self.discs[player].count_ones()
+    }
+
+    /// Squares where the player to move can place a disc.
+    fn moves(&self) -> u64 {
+        let player = self.current_player();
+        legal_moves(self.discs[player], self.discs[1 - player])
+    }
+
+    /// Whether the opponent of the player to move can place a disc.
+    fn opponent_can_move(&self) -> bool {
+        let player = self.current_player();
+        legal_moves(self.discs[1 - player], self.discs[player]) != 0
+    }
+}
+
+impl Default for Othello {
+    fn default() -> Othello {
+        Othello::new()
+    }
+}
+
+impl fmt::Display for Othello {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..8 {
+            for col in 0..8 {
+                let bit = 1 << (8 * row + col);
+                let c = if self.discs[0] & bit != 0 {
+                    'x'
+                } else if self.discs[1] & bit != 0 {
+                    'o'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game<Action> for Othello {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        let mut moves = self.moves();
+        if moves == 0 {
+            if self.opponent_can_move() {
+                actions.push(Action::Pass);
+            }
+            return;
+        }
+        while moves != 0 {
+            actions.push(Action::Place(moves.trailing_zeros() as u8));
+            moves &= moves - 1;
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.moves() == 0 && !self.opponent_can_move()
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        let mut moves = self.moves();
+        if moves == 0 {
+            return if self.opponent_can_move() { Some(Action::Pass) } else { None };
+        }
+        for _ in 0..rng.gen_range(0, moves.count_ones()) {
+            moves &= moves - 1;
+        }
+        Some(Action::Place(moves.trailing_zeros() as u8))
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        let player = self.current_player();
+        let moves = self.moves();
+        match *action {
+            Action::Place(square) if square < 64 && moves & (1 << square) != 0 => {
+                let bit = 1 << square;
+                let flips = flipped(self.discs[player], self.discs[1 - player], bit);
+                self.discs[player] |= bit | flips;
+                self.discs[1 - player] &= !flips;
+            },
+            Action::Pass if moves == 0 && self.opponent_can_move() => {},
+            _ => return Err(GameError::IllegalMove(format!("{}", action))),
+        }
+        self.to_move = 1 - self.to_move;
+        Ok(())
+    }
+
+    /// Disc difference of black.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.to_move as usize
+    }
+
+    fn reward_for(&self, player: usize) -> f32 {
+        (self.disc_count(player) as f32 - self.disc_count(1 - player) as f32) / 64.
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use test::Bencher;
+
+    use agent::{Budget, MctsAgent, RandomAgent};
+    use arena::Arena;
+    use mcts::*;
+    use othello::*;
+    use utils::seeded_rng;
+
+    fn square(s: &str) -> Action {
+        s.parse().unwrap()
+    }
+
+    fn perft(game: &Othello, depth: usize) -> usize {
+        if depth == 0 {
+            return 1;
+        }
+        game.allowed_actions().iter()
+            .map(|action| {
+                let mut next = *game;
+                next.make_move(action);
+                perft(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_action() {
+        assert_eq!(square("a1"), Action::Place(0));
+        assert_eq!(square("h8"), Action::Place(63));
+        assert_eq!(square("d3"), Action::at(2, 3));
+        assert_eq!(format!("{}", Action::at(2, 3)), "d3");
+        assert_eq!(square("pass"), Action::Pass);
+        assert!("i1".parse::<Action>().is_err());
+        assert!("a9".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn test_start() {
+        let game = Othello::new();
+        let mut actions = game.allowed_actions().iter().map(|a| format!("{}", a)).collect::<Vec<_>>();
+        actions.sort();
+        assert_eq!(actions, vec!["c4", "d3", "e6", "f5"]);
+        assert_eq!((game.disc_count(0), game.disc_count(1)), (2, 2));
+        assert_eq!(game.reward(), 0.);
+    }
+
+    #[test]
+    fn test_flipping() {
+        let mut game = Othello::new();
+        game.make_move(&square("d3"));
+        assert_eq!(format!("{}", game), "........\n........\n...x....\n...xx...\n...xo...\n........\n........\n........\n");
+        assert_eq!(game.current_player(), 1);
+
+        // Lines in several directions flip at once, but not past an empty square
+        let board = "\
+            x.x..x..\
+            .ooo....\
+            xo.oooox\
+            .ooo....\
+            x.x.x...\
+            ........\
+            ........\
+            ........";
+        let mut game = Othello::from_board(board, 0).unwrap();
+        game.make_move(&square("c3"));
+        let expected = "\
+            x.x..x..\
+            .xxo....\
+            xxxxxxxx\
+            .xxx....\
+            x.x.x...\
+            ........\
+            ........\
+            ........";
+        assert_eq!(game, Othello::from_board(expected, 1).unwrap());
+
+        // Placing needs to flip something
+        let mut game = Othello::new();
+        assert!(game.try_make_move(&square("a1")).is_err());
+        assert!(game.try_make_move(&square("d4")).is_err());
+        assert!(game.try_make_move(&Action::Pass).is_err());
+    }
+
+    #[test]
+    fn test_forced_pass() {
+        // White has no move, black does
+        let board = "\
+            xo......\
+            ........\
+            ........\
+            ........\
+            ........\
+            ........\
+            ........\
+            ........";
+        let mut game = Othello::from_board(board, 1).unwrap();
+        assert!(!game.is_terminal());
+        assert_eq!(game.allowed_actions(), vec![Action::Pass]);
+        assert_eq!(game.random_action(&mut seeded_rng(0, 0)), Some(Action::Pass));
+        assert!(game.try_make_move(&square("c1")).is_err());
+        game.make_move(&Action::Pass);
+        assert_eq!(game.current_player(), 0);
+        assert_eq!(game.allowed_actions(), vec![square("c1")]);
+
+        // Now neither player can move
+        game.make_move(&square("c1"));
+        assert!(game.is_terminal());
+        assert!(game.allowed_actions().is_empty());
+        assert!(game.try_make_move(&Action::Pass).is_err());
+    }
+
+    #[test]
+    fn test_game_end() {
+        let board = "\
+            xxxxxxxx\
+            xxxxxxxx\
+            xxxxxxxx\
+            xxxxxxxx\
+            xooooooo\
+            oooooooo\
+            oooooooo\
+            oooooooo";
+        let game = Othello::from_board(board, 1).unwrap();
+        assert!(game.is_terminal());
+        assert_eq!((game.reward_for(0), game.reward_for(1)), (2. / 64., -2. / 64.));
+        assert_eq!(game.decided_reward(), Some(2. / 64.));
+
+        // A random game ends with all discs accounted for
+        let end = playout(&Othello::new(), None, &mut seeded_rng(0, 0)).unwrap();
+        assert!(end.is_terminal());
+        assert!(end.disc_count(0) + end.disc_count(1) <= 64);
+        assert_eq!(end.reward_for(0), -end.reward_for(1));
+    }
+
+    #[test]
+    fn test_perft() {
+        // Published node counts from the starting position
+        let counts = (1..7).map(|depth| perft(&Othello::new(), depth)).collect::<Vec<_>>();
+        assert_eq!(counts, vec![4, 12, 56, 244, 1396, 8200]);
+    }
+
+    #[test]
+    fn test_against_random() {
+        let arena = Arena::new(&Othello::new());
+        let mut mcts = MctsAgent::new(1, Budget::Iterations(200), 1.);
+        let mut random = RandomAgent::new();
+        let results = arena.run(&mut [&mut mcts, &mut random], 4).unwrap();
+        assert!(results[0].wins >= 3, "{:?}", results);
+    }
+
+    #[bench]
+    fn bench_playout(b: &mut Bencher) {
+        let game = Othello::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
+    }
+
+    #[bench]
+    fn bench_fill_actions(b: &mut Bencher) {
+        let game = Othello::new();
+        let mut actions = Vec::new();
+        b.iter(|| {
+            game.fill_actions(&mut actions);
+            actions.len()
+        });
+    }
+}
@@ -0,0 +1,434 @@
+//!
+//! Pig, a two-player dice game.
+//!
+//! The player to move rolls a die as often as they like, adding the pips to
+//! a turn total. Rolling a 1 loses the turn total and ends the turn; holding
+//! adds the turn total to the player's score and passes the die. The first
+//! player to reach the target score, 100 by default, wins. Every turn starts
+//! with a roll, and the game ends as soon as score and turn total reach the
+//! target, since holding would win at once.
+//!
+//! Like in `TwoFortyEight` the dice come from a random number generator
+//! inside the game, so `set_rng_seed` fixes all future rolls. The search
+//! therefore sees one determinization per tree of the ensemble, in which
+//! the outcome of every roll is known in advance.
+//!
+//! The optimal policy follows from value iteration over the probabilities
+//! to win [1]; `OptimalPolicy` computes it for the search to be measured
+//! against. For a target of 100 the first player wins with probability
+//! 0.5306 under optimal play.
+//!
+//! [1] T. W. Neller, C. G. M. Presser: Optimal Play of the Dice Game Pig (UMAP Journal, 2004)
+//!
+
+use std::fmt;
+
+use rand::{Rng, XorShiftRng};
+
+use agent::Agent;
+use error::GameError;
+use mcts::{GameAction, Game};
+use utils::seeded_rng;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Action {
+    Roll,
+    Hold,
+}
+impl GameAction for Action {}
+
+#[derive(Clone)]
+pub struct Pig {
+    target: u32,
+    scores: [u32; 2],
+    turn_total: u32,
+    to_move: u8,
+    last_roll: Option<u8>,
+    rng: XorShiftRng,
+}
+
+impl Pig {
+    /// A game to the given target score.
+    pub fn new(target: u32) -> Result<Pig, GameError> {
+        if target == 0 {
+            return Err(GameError::Other("the target score must be positive".to_string()));
+        }
+        Ok(Pig {
+            target,
+            scores: [0, 0],
+            turn_total: 0,
+            to_move: 0,
+            last_roll: None,
+            rng: seeded_rng(0, 0),
+        })
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Banked score of `player`.
+    pub fn score(&self, player: usize) -> u32 {
+        self.scores[player]
+    }
+
+    /// Points of the current turn, lost on rolling a 1.
+    pub fn turn_total(&self) -> u32 {
+        self.turn_total
+    }
+
+    /// The pips of the last roll.
+    pub fn last_roll(&self) -> Option<u8> {
+        self.last_roll
+    }
+
+    /// The player who reached the target, if any.
+    pub fn winner(&self) -> Option<usize> {
+        let player = self.current_player();
+        if self.scores[player] + self.turn_total >= self.target {
+            Some(player)
+        } else {
+            None
+        }
+    }
+
+    fn pass(&mut self) {
+        self.turn_total = 0;
+        self.to_move = 1 - self.to_move;
+    }
+}
+
+impl Default for Pig {
+    fn default() -> Pig {
+        Pig::new(100).unwrap()
+    }
+}
+
+impl fmt::Display for Pig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{} to {}, player {} at {}",
+               self.scores[0], self.scores[1], self.target, self.to_move, self.turn_total)
+    }
+}
+
+impl Game<Action> for Pig {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        if self.winner().is_some() {
+            return;
+        }
+        actions.push(Action::Roll);
+        if self.turn_total > 0 {
+            actions.push(Action::Hold);
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.winner().is_some()
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        if self.winner().is_some() || (*action == Action::Hold && self.turn_total == 0) {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        match *action {
+            Action::Roll => {
+                let roll = self.rng.gen_range(1, 7);
+                self.last_roll = Some(roll);
+                if roll == 1 {
+                    self.pass();
+                } else {
+                    self.turn_total += roll as u32;
+                }
+            },
+            Action::Hold => {
+                self.scores[self.to_move as usize] += self.turn_total;
+                self.pass();
+            },
+        }
+        Ok(())
+    }
+
+    /// Reward of the first player.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    /// Reseed the dice for all future rolls.
+    fn set_rng_seed(&mut self, seed: u32) {
+        self.rng = seeded_rng(seed, 0);
+    }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.to_move as usize
+    }
+
+    /// 1 for the winner, -1 for the loser; 0 before the end.
+    fn reward_for(&self, player: usize) -> f32 {
+        match self.winner() {
+            Some(winner) if winner == player => 1.,
+            Some(_) => -1.,
+            None => 0.,
+        }
+    }
+}
+
+/// The policy maximizing the probability to win, found by value iteration.
+///
+/// Stores a probability for every score of the player to move, score of the
+/// opponent and turn total, i.e. the cube of the target score.
+#[derive(Debug, Clone)]
+pub struct OptimalPolicy {
+    target: usize,
+    wins: Vec<f64>,
+}
+
+impl OptimalPolicy {
+    pub fn new(target: u32) -> OptimalPolicy {
+        let n = target as usize;
+        let mut policy = OptimalPolicy {
+            target: n,
+            wins: vec![0.; n * n * n],
+        };
+        // Holding leads to a higher sum of scores, rolling keeps it; so the
+        // sums are solved from the highest down, each one iterated until
+        // it converges.
+        for sum in (0..2 * n - 1).rev() {
+            loop {
+                let mut change = 0f64;
+                for i in sum.saturating_sub(n - 1)..n.min(sum + 1) {
+                    let j = sum - i;
+                    for k in (0..n - i).rev() {
+                        let (roll, hold) = policy.values(i, j, k);
+                        let value = roll.max(hold);
+                        let index = (i * n + j) * n + k;
+                        change = change.max((value - policy.wins[index]).abs());
+                        policy.wins[index] = value;
+                    }
+                }
+                if change < 1e-9 {
+                    break;
+                }
+            }
+        }
+        policy
+    }
+
+    /// Probability to win for the player to move with score `i` and turn
+    /// total `k` against an opponent with score `j`.
+    fn win(&self, i: usize, j: usize, k: usize) -> f64 {
+        if i + k >= self.target {
+            1.
+        } else {
+            self.wins[(i * self.target + j) * self.target + k]
+        }
+    }
+
+    /// Probabilities to win when rolling and when holding.
+    fn values(&self, i: usize, j: usize, k: usize) -> (f64, f64) {
+        let roll = ((1. - self.win(j, i, 0)) + (2..7).map(|pips| self.win(i, j, k + pips)).sum::<f64>()) / 6.;
+        let hold = if k > 0 { 1. - self.win(j, i + k, 0) } else { 0. };
+        (roll, hold)
+    }
+
+    fn state(&self, game: &Pig) -> (usize, usize, usize) {
+        assert_eq!(game.target() as usize, self.target, "policy for a different target");
+        let player = game.current_player();
+        (game.score(player) as usize, game.score(1 - player) as usize, game.turn_total() as usize)
+    }
+
+    /// Probability that the player to move wins with optimal play.
+    pub fn win_probability(&self, game: &Pig) -> f64 {
+        let (i, j, k) = self.state(game);
+        self.win(i, j, k)
+    }
+
+    /// Probabilities to win after rolling and after holding.
+    pub fn action_values(&self, game: &Pig) -> (f64, f64) {
+        let (i, j, k) = self.state(game);
+        self.values(i, j, k)
+    }
+
+    /// The optimal action, or `None` if the game is over.
+    pub fn best_action(&self, game: &Pig) -> Option<Action> {
+        if game.is_terminal() {
+            return None;
+        }
+        let (roll, hold) = self.action_values(game);
+        Some(if hold > roll { Action::Hold } else { Action::Roll })
+    }
+}
+
+impl Agent<Pig, Action> for OptimalPolicy {
+    fn name(&self) -> String {
+        "Optimal".to_string()
+    }
+
+    fn choose_action(&mut self, game: &Pig) -> Result<Option<Action>, GameError> {
+        Ok(self.best_action(game))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+    use test::Bencher;
+
+    use agent::*;
+    use arena::*;
+    use mcts::*;
+    use pig::*;
+    use utils::seeded_rng;
+
+    fn state(target: u32, scores: [u32; 2], turn_total: u32, to_move: u8) -> Pig {
+        let mut game = Pig::new(target).unwrap();
+        game.scores = scores;
+        game.turn_total = turn_total;
+        game.to_move = to_move;
+        game
+    }
+
+    /// Roll until the last roll was `pips`.
+    fn roll_until(game: &mut Pig, pips: u8) {
+        loop {
+            game.make_move(&Action::Roll);
+            if game.last_roll() == Some(pips) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut game = Pig::new(1000).unwrap();
+        assert!(Pig::new(0).is_err());
+        assert_eq!(game.allowed_actions(), vec![Action::Roll]);
+        assert!(game.try_make_move(&Action::Hold).is_err());
+
+        // A one loses the turn total
+        roll_until(&mut game, 1);
+        assert_eq!((game.current_player(), game.turn_total(), game.score(0)), (1, 0, 0));
+
+        game.make_move(&Action::Roll);
+        while game.last_roll() == Some(1) || game.turn_total() == 0 {
+            game.make_move(&Action::Roll);
+        }
+        let player = game.current_player();
+        let total = game.turn_total();
+        assert_eq!(game.allowed_actions(), vec![Action::Roll, Action::Hold]);
+        game.make_move(&Action::Hold);
+        assert_eq!((game.score(player), game.turn_total()), (total, 0));
+        assert_eq!(game.current_player(), 1 - player);
+
+        // Reaching the target ends the game without holding
+        let mut game = state(10, [8, 5], 0, 1);
+        while !game.is_terminal() {
+            game.make_move(&Action::Roll);
+        }
+        let winner = game.winner().unwrap();
+        assert!(game.score(winner) + game.turn_total() >= 10);
+        assert_eq!((game.reward_for(winner), game.reward_for(1 - winner)), (1., -1.));
+        assert_eq!(game.decided_reward(), Some(game.reward_for(0)));
+        assert!(game.allowed_actions().is_empty());
+        assert!(game.try_make_move(&Action::Roll).is_err());
+    }
+
+    #[test]
+    fn test_dice() {
+        let rolls = |seed| {
+            let mut game = Pig::new(1000).unwrap();
+            game.set_rng_seed(seed);
+            (0..50).map(|_| {
+                game.make_move(&Action::Roll);
+                game.last_roll().unwrap()
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(3), rolls(3));
+        assert!(rolls(3) != rolls(4));
+        assert!((1..7).all(|pips| rolls(0).contains(&pips)));
+    }
+
+    #[test]
+    fn test_optimal_policy() {
+        // To 2 points the first roll decides unless it is a one: p = 5/6 + p'/6 with p' = 1 - p
+        let policy = OptimalPolicy::new(2);
+        assert!((policy.win_probability(&Pig::new(2).unwrap()) - 6. / 7.).abs() < 1e-6);
+
+        let policy = OptimalPolicy::new(100);
+        let game = Pig::default();
+        assert!((policy.win_probability(&game) - 0.5306).abs() < 1e-4);
+        // Hold at 20 or more when both players start, roll below
+        assert_eq!(policy.best_action(&state(100, [0, 0], 19, 0)), Some(Action::Roll));
+        assert_eq!(policy.best_action(&state(100, [0, 0], 25, 0)), Some(Action::Hold));
+        // Far behind, keep rolling
+        assert_eq!(policy.best_action(&state(100, [0, 90], 30, 0)), Some(Action::Roll));
+        assert_eq!(policy.best_action(&state(100, [0, 90], 0, 1)), Some(Action::Roll));
+    }
+
+    #[test]
+    fn test_mcts_agrees_with_optimal_policy() {
+        let target = 20;
+        let policy = OptimalPolicy::new(target);
+        let mut rng = seeded_rng(0, 0);
+        let (mut tested, mut agreed) = (0, 0);
+        while tested < 20 {
+            let game = state(target, [rng.gen_range(0, target), rng.gen_range(0, target)],
+                             rng.gen_range(1, 12), rng.gen_range(0, 2));
+            if game.is_terminal() {
+                continue;
+            }
+            // Only decisions that matter
+            let (roll, hold) = policy.action_values(&game);
+            if (roll - hold).abs() < 0.05 {
+                continue;
+            }
+            let mut mcts = MCTS::with_seed(&game, 16, tested);
+            mcts.search(200, 1.).unwrap();
+            if mcts.best_action() == policy.best_action(&game) {
+                agreed += 1;
+            }
+            tested += 1;
+        }
+        assert!(agreed >= 17, "{} of 20", agreed);
+    }
+
+    #[test]
+    fn test_optimal_beats_random() {
+        let game = Pig::default();
+        let mut optimal = OptimalPolicy::new(100);
+        let mut random = RandomAgent::new();
+        let results = Arena::new(&game).run(&mut [&mut optimal, &mut random], 20).unwrap();
+        assert!(results[0].wins >= 16, "{:?}", results);
+    }
+
+    #[bench]
+    fn bench_playout(b: &mut Bencher) {
+        let game = Pig::default();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
+    }
+}
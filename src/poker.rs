@@ -0,0 +1,544 @@
+//!
+//! Kuhn and Leduc poker: small two-player games with hidden cards.
+//!
+//! Both players ante one chip and get one private card. In Kuhn poker the
+//! deck holds a jack, a queen and a king, and there is a single betting
+//! round with bets of one chip and at most one bet. Leduc poker uses two
+//! jacks, queens and kings; after the first betting round a public board
+//! card is dealt and a second round follows. Bets are two chips in the
+//! first and four in the second round, with at most two raises per round.
+//! At the showdown a private card pairing the board wins, otherwise the
+//! higher card; equal cards split the pot. Rewards are the chips won or
+//! lost, so the game is zero-sum. Player 0 acts first in every round.
+//!
+//! `set_rng_seed` deals a new hand as long as nobody has acted yet;
+//! afterwards it only changes the board card still to come. `determinize`
+//! keeps the cards the observer has seen and deals the opponent's card
+//! anew, uniformly from the rest of the deck; it does not weigh the cards
+//! by the opponent's actions.
+//!
+//! The values of the games for player 0 under optimal play are -1/18 for
+//! Kuhn [1] and about -0.086 for Leduc poker [2].
+//!
+//! [1] H. W. Kuhn: A Simplified Two-Person Poker (Contributions to the Theory of Games, 1950)
+//! [2] F. Southey et al.: Bayes' Bluff: Opponent Modelling in Poker (UAI, 2005)
+//!
+
+use std::cmp::Ordering;
+
+use rand::{Rng, XorShiftRng};
+
+use error::GameError;
+use mcts::{GameAction, Game};
+use utils::seeded_rng;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Action {
+    /// Give up the hand; only allowed when facing a bet.
+    Fold,
+    /// Match the opponent's bet, or check if there is none.
+    Call,
+    /// Bet, or raise the opponent's bet.
+    Raise,
+}
+impl GameAction for Action {}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Variant {
+    Kuhn,
+    Leduc,
+}
+
+impl Variant {
+    /// Ranks of the cards in the deck: 0 jack, 1 queen, 2 king.
+    fn deck(&self) -> &'static [u8] {
+        match *self {
+            Variant::Kuhn => &[0, 1, 2],
+            Variant::Leduc => &[0, 0, 1, 1, 2, 2],
+        }
+    }
+
+    fn rounds(&self) -> u8 {
+        match *self {
+            Variant::Kuhn => 1,
+            Variant::Leduc => 2,
+        }
+    }
+
+    fn bet_size(&self, round: u8) -> u32 {
+        match *self {
+            Variant::Kuhn => 1,
+            Variant::Leduc => 2 << round,
+        }
+    }
+
+    fn max_raises(&self) -> u8 {
+        match *self {
+            Variant::Kuhn => 1,
+            Variant::Leduc => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Poker {
+    variant: Variant,
+    rng: XorShiftRng,
+    cards: [u8; 2],                     // private card of each player
+    board: Option<u8>,                  // public card (Leduc, second round)
+    contributions: [u32; 2],            // chips in the pot from each player
+    round: u8,
+    raises: u8,                         // raises in the current round
+    acted: u8,                          // actions in the current round
+    to_move: u8,
+    folded: Option<u8>,
+    finished: bool,
+}
+
+/// Hands are equal when cards and betting agree; the state of the random
+/// number generator, which only affects future deals, is ignored.
+impl PartialEq for Poker {
+    fn eq(&self, other: &Poker) -> bool {
+        self.variant == other.variant
+            && self.cards == other.cards
+            && self.board == other.board
+            && self.contributions == other.contributions
+            && self.round == other.round
+            && self.raises == other.raises
+            && self.acted == other.acted
+            && self.to_move == other.to_move
+            && self.folded == other.folded
+            && self.finished == other.finished
+    }
+}
+
+impl Eq for Poker {}
+
+impl Poker {
+    /// A hand of Kuhn poker dealt with seed 0.
+    pub fn kuhn() -> Poker {
+        Poker::new(Variant::Kuhn)
+    }
+
+    /// A hand of Leduc poker dealt with seed 0.
+    pub fn leduc() -> Poker {
+        Poker::new(Variant::Leduc)
+    }
+
+    pub fn new(variant: Variant) -> Poker {
+        let mut game = Poker {
+            variant,
+            rng: seeded_rng(0, 0),
+            cards: [0, 0],
+            board: None,
+            contributions: [1, 1],
+            round: 0,
+            raises: 0,
+            acted: 0,
+            to_move: 0,
+            folded: None,
+            finished: false,
+        };
+        game.deal();
+        game
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// The private card of `player`; only to be looked at by that player.
+    pub fn card(&self, player: usize) -> u8 {
+        self.cards[player]
+    }
+
+    /// The public card, once dealt.
+    pub fn board(&self) -> Option<u8> {
+        self.board
+    }
+
+    /// Chips put into the pot by `player`, including the ante.
+    pub fn contribution(&self, player: usize) -> u32 {
+        self.contributions[player]
+    }
+
+    /// Has anybody acted yet?
+    fn started(&self) -> bool {
+        self.round > 0 || self.acted > 0
+    }
+
+    /// The cards of the deck except for `seen`.
+    fn remaining(&self, seen: &[u8]) -> Vec<u8> {
+        let mut deck = self.variant.deck().to_vec();
+        for card in seen {
+            if let Some(i) = deck.iter().position(|c| c == card) {
+                deck.swap_remove(i);
+            }
+        }
+        deck
+    }
+
+    fn draw(&mut self, seen: &[u8]) -> u8 {
+        let deck = self.remaining(seen);
+        deck[self.rng.gen_range(0, deck.len())]
+    }
+
+    fn deal(&mut self) {
+        self.cards[0] = self.draw(&[]);
+        let first = self.cards[0];
+        self.cards[1] = self.draw(&[first]);
+    }
+
+    /// Strength of the hand of `player` at the showdown.
+    fn strength(&self, player: usize) -> u8 {
+        let card = self.cards[player];
+        if self.board == Some(card) { 3 + card } else { card }
+    }
+
+    fn end_round(&mut self) {
+        if self.round + 1 == self.variant.rounds() {
+            self.finished = true;
+            return;
+        }
+        let cards = self.cards;
+        self.board = Some(self.draw(&cards));
+        self.round += 1;
+        self.raises = 0;
+        self.acted = 0;
+        self.to_move = 0;
+    }
+}
+
+impl Game<Action> for Poker {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        if self.finished {
+            return;
+        }
+        let player = self.current_player();
+        if self.contributions[player] < self.contributions[1 - player] {
+            actions.push(Action::Fold);
+        }
+        actions.push(Action::Call);
+        if self.raises < self.variant.max_raises() {
+            actions.push(Action::Raise);
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.finished
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        let player = self.current_player();
+        let facing_bet = self.contributions[player] < self.contributions[1 - player];
+        let allowed = !self.finished && match *action {
+            Action::Fold => facing_bet,
+            Action::Call => true,
+            Action::Raise => self.raises < self.variant.max_raises(),
+        };
+        if !allowed {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+
+        self.acted += 1;
+        match *action {
+            Action::Fold => {
+                self.folded = Some(player as u8);
+                self.finished = true;
+            },
+            Action::Call => {
+                self.contributions[player] = self.contributions[1 - player];
+                if facing_bet || self.acted >= 2 {
+                    self.end_round();
+                } else {
+                    self.to_move = 1 - self.to_move;
+                }
+            },
+            Action::Raise => {
+                self.contributions[player] = self.contributions[1 - player] + self.variant.bet_size(self.round);
+                self.raises += 1;
+                self.to_move = 1 - self.to_move;
+            },
+        }
+        Ok(())
+    }
+
+    /// Chips won by player 0.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    /// Deal a new hand if nobody has acted yet; otherwise only reseed the
+    /// board card to come.
+    fn set_rng_seed(&mut self, seed: u32) {
+        self.rng = seeded_rng(seed, 0);
+        if !self.started() {
+            self.deal();
+        }
+    }
+
+    fn determinize(&mut self, observer: usize, seed: u32) {
+        self.rng = seeded_rng(seed, 0);
+        let mut seen = vec![self.cards[observer]];
+        seen.extend(self.board);
+        self.cards[1 - observer] = self.draw(&seen);
+    }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.finished {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.to_move as usize
+    }
+
+    /// Chips won or lost by `player`; 0 before the end.
+    fn reward_for(&self, player: usize) -> f32 {
+        if !self.finished {
+            return 0.;
+        }
+        let opponent = 1 - player;
+        match self.folded {
+            Some(folder) if folder as usize == player => -(self.contributions[player] as f32),
+            Some(_) => self.contributions[opponent] as f32,
+            None => match self.strength(player).cmp(&self.strength(opponent)) {
+                Ordering::Greater => self.contributions[opponent] as f32,
+                Ordering::Less => -(self.contributions[player] as f32),
+                Ordering::Equal => 0.,
+            },
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mcts::*;
+    use poker::*;
+
+    const J: u8 = 0;
+    const Q: u8 = 1;
+    const K: u8 = 2;
+
+    fn hand(variant: Variant, cards: [u8; 2], actions: &[Action]) -> Poker {
+        let mut game = Poker::new(variant);
+        game.cards = cards;
+        for action in actions {
+            game.make_move(action);
+        }
+        game
+    }
+
+    struct InfoSet {
+        regrets: Vec<f64>,
+        strategy_sum: Vec<f64>,
+    }
+
+    fn regret_matching(regrets: &[f64]) -> Vec<f64> {
+        let positive = regrets.iter().map(|r| r.max(0.)).collect::<Vec<_>>();
+        let sum = positive.iter().sum::<f64>();
+        if sum > 0. {
+            positive.iter().map(|r| r / sum).collect()
+        } else {
+            vec![1. / regrets.len() as f64; regrets.len()]
+        }
+    }
+
+    fn info_key(game: &Poker, history: &str) -> String {
+        format!("{}{:?}:{}", game.card(game.current_player()), game.board(), history)
+    }
+
+    /// Counterfactual regret minimization; returns the value for player 0.
+    fn cfr(game: &Poker, history: &str, reach: [f64; 2], sets: &mut HashMap<String, InfoSet>) -> f64 {
+        if game.is_terminal() {
+            return game.reward() as f64;
+        }
+        let player = game.current_player();
+        let actions = game.allowed_actions();
+        let key = info_key(game, history);
+        let strategy = {
+            let set = sets.entry(key.clone()).or_insert_with(|| InfoSet {
+                regrets: vec![0.; actions.len()],
+                strategy_sum: vec![0.; actions.len()],
+            });
+            regret_matching(&set.regrets)
+        };
+
+        let mut values = Vec::new();
+        let mut value = 0.;
+        for (action, p) in actions.iter().zip(&strategy) {
+            let mut next = game.clone();
+            next.make_move(action);
+            let mut next_reach = reach;
+            next_reach[player] *= p;
+            let v = cfr(&next, &format!("{}{:?}", history, action), next_reach, sets);
+            values.push(v);
+            value += p * v;
+        }
+
+        let set = sets.get_mut(&key).unwrap();
+        let sign = if player == 0 { 1. } else { -1. };
+        for i in 0..actions.len() {
+            set.regrets[i] += reach[1 - player] * sign * (values[i] - value);
+            set.strategy_sum[i] += reach[player] * strategy[i];
+        }
+        value
+    }
+
+    /// Value for player 0 when both play the average CFR strategy.
+    fn average_value(game: &Poker, history: &str, sets: &HashMap<String, InfoSet>) -> f64 {
+        if game.is_terminal() {
+            return game.reward() as f64;
+        }
+        let set = &sets[&info_key(game, history)];
+        let total = set.strategy_sum.iter().sum::<f64>();
+        game.allowed_actions().iter().zip(&set.strategy_sum)
+            .map(|(action, s)| {
+                let mut next = game.clone();
+                next.make_move(action);
+                s / total * average_value(&next, &format!("{}{:?}", history, action), sets)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_kuhn_rules() {
+        use poker::Action::*;
+        let game = hand(Variant::Kuhn, [K, J], &[]);
+        assert_eq!(game.allowed_actions(), vec![Call, Raise]);
+
+        // check, check: showdown for the antes
+        assert_eq!(hand(Variant::Kuhn, [K, J], &[Call, Call]).reward(), 1.);
+        // check, bet, fold
+        let game = hand(Variant::Kuhn, [K, J], &[Call, Raise]);
+        assert_eq!(game.allowed_actions(), vec![Fold, Call]);
+        let mut folded = game.clone();
+        folded.make_move(&Fold);
+        assert!(folded.is_terminal());
+        assert_eq!((folded.reward_for(0), folded.reward_for(1)), (-1., 1.));
+        // check, bet, call
+        let mut called = game.clone();
+        called.make_move(&Call);
+        assert_eq!(called.reward(), 2.);
+        // bet, call with the worse card
+        assert_eq!(hand(Variant::Kuhn, [J, Q], &[Raise, Call]).reward_for(1), 2.);
+
+        let mut game = hand(Variant::Kuhn, [J, Q], &[Raise]);
+        assert!(game.try_make_move(&Raise).is_err());
+        game.make_move(&Fold);
+        assert!(game.allowed_actions().is_empty());
+        assert!(game.try_make_move(&Call).is_err());
+    }
+
+    #[test]
+    fn test_leduc_rules() {
+        use poker::Action::*;
+        let mut game = hand(Variant::Leduc, [Q, K], &[Raise, Raise]);
+        assert_eq!((game.contribution(0), game.contribution(1)), (3, 5));
+        assert_eq!(game.allowed_actions(), vec![Fold, Call]);
+        game.make_move(&Call);
+        assert!(!game.is_terminal());
+        let board = game.board().unwrap();
+        assert_eq!(game.current_player(), 0);
+
+        // Bets of four in the second round
+        game.make_move(&Raise);
+        assert_eq!(game.contribution(0), 9);
+        game.make_move(&Call);
+        assert!(game.is_terminal());
+        let expected = if board == Q { 9. } else { -9. };
+        assert_eq!(game.reward(), expected);
+        assert_eq!(game.reward_for(1), -expected);
+
+        // A pair beats a higher card, equal cards split
+        let mut game = hand(Variant::Leduc, [J, K], &[Call, Call]);
+        game.board = Some(J);
+        game.make_move(&Call);
+        game.make_move(&Call);
+        assert_eq!(game.reward(), 1.);
+        let mut game = hand(Variant::Leduc, [Q, Q], &[Call, Call, Call, Call]);
+        game.board = Some(K);
+        assert_eq!(game.reward(), 0.);
+    }
+
+    #[test]
+    fn test_dealing() {
+        let mut game = Poker::leduc();
+        let mut deals = HashMap::new();
+        for seed in 0..100 {
+            game.set_rng_seed(seed);
+            *deals.entry(game.cards).or_insert(0) += 1;
+        }
+        assert_eq!(deals.len(), 9);
+
+        // Once the hand started the cards stay
+        game.make_move(&Action::Call);
+        let cards = game.cards;
+        let before = game.clone();
+        game.set_rng_seed(1000);
+        assert_eq!(game.cards, cards);
+        assert_eq!(game, before);
+
+        // Determinization keeps what the observer sees
+        game.make_move(&Action::Call);
+        let board = game.board().unwrap();
+        let mut seen = HashMap::new();
+        for seed in 0..100 {
+            let mut sample = game.clone();
+            sample.determinize(0, seed);
+            assert_eq!((sample.card(0), sample.board()), (cards[0], Some(board)));
+            *seen.entry(sample.card(1)).or_insert(0) += 1;
+        }
+        let possible = game.remaining(&[cards[0], board]);
+        assert!(seen.keys().all(|card| possible.contains(card)));
+        assert_eq!(seen.len(), possible.iter().collect::<::std::collections::HashSet<_>>().len());
+    }
+
+    #[test]
+    fn test_kuhn_value() {
+        let mut sets = HashMap::new();
+        let deals = [[J, Q], [J, K], [Q, J], [Q, K], [K, J], [K, Q]];
+        for _ in 0..2000 {
+            for &cards in &deals {
+                cfr(&hand(Variant::Kuhn, cards, &[]), "", [1., 1.], &mut sets);
+            }
+        }
+        let value = deals.iter()
+                .map(|&cards| average_value(&hand(Variant::Kuhn, cards, &[]), "", &sets))
+                .sum::<f64>() / deals.len() as f64;
+        assert!((value + 1. / 18.).abs() < 0.005, "{}", value);
+    }
+
+    #[test]
+    fn test_determinized_mcts() {
+        // Facing a bet, call with the king and fold the jack whatever the opponent holds
+        for &(card, expected) in &[(K, Action::Call), (J, Action::Fold)] {
+            let game = hand(Variant::Kuhn, [card, Q], &[Action::Call, Action::Raise]);
+            let mut mcts = MCTS::with_seed(&game, 8, 0);
+            mcts.search(100, 1.).unwrap();
+            assert_eq!(mcts.best_action(), Some(expected));
+        }
+    }
+}
@@ -0,0 +1,546 @@
+//!
+//! SameGame, a single-player puzzle and a common benchmark for single-player
+//! MCTS [1] and nested Monte Carlo search [2].
+//!
+//! The board is filled with stones of several colors, 15x15 stones of five
+//! colors in the usual setting. A move removes a group of at least two
+//! orthogonally connected stones of the same color; the stones above fall
+//! down and empty columns are closed by shifting the columns on their right
+//! to the left. Removing `n` stones scores `(n - 2)²` points, and clearing
+//! the whole board earns a bonus of 1000. The game ends when no group is
+//! left; `(n - 2)²` points are then deducted for each color with `n` stones
+//! left, as if they formed one group.
+//!
+//! The published results use a fixed set of 20 positions from [1], which is
+//! not bundled here; `read_positions` loads it from its text form, one row
+//! of color digits per line, top row first, with boards separated by blank
+//! lines.
+//!
+//! [1] M. P. D. Schadd et al.: Single-Player Monte-Carlo Tree Search (CG 2008)
+//! [2] T. Cazenave: Nested Monte-Carlo Search (IJCAI 2009)
+//!
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+use utils::seeded_rng;
+
+/// Colors are written as single digits.
+const MAX_COLORS: usize = 10;
+const CLEAR_BONUS: i32 = 1000;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+/// Remove the group containing the stone in column `col` and row `row`,
+/// rows counted from the bottom.
+pub struct Action {
+    pub col: u8,
+    pub row: u8,
+}
+impl GameAction for Action {}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SameGame {
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,         // column by column from the bottom; 0 empty, else color + 1
+    score: i32,
+    moves: usize,
+}
+
+impl SameGame {
+    /// A random 15x15 board with five colors.
+    pub fn new(seed: u32) -> SameGame {
+        SameGame::random(15, 15, 5, seed).unwrap()
+    }
+
+    /// A random board of the given size and number of colors.
+    pub fn random(width: usize, height: usize, colors: usize, seed: u32) -> Result<SameGame, GameError> {
+        if width == 0 || height == 0 || width > 256 || height > 256 {
+            return Err(GameError::Other(format!("invalid board size {}x{}", width, height)));
+        }
+        if colors == 0 || colors > MAX_COLORS {
+            return Err(GameError::Other(format!("invalid number of colors {}", colors)));
+        }
+        let mut rng = seeded_rng(seed, 0);
+        Ok(SameGame {
+            width,
+            height,
+            cells: (0..width * height).map(|_| rng.gen_range(1, colors as u8 + 1)).collect(),
+            score: 0,
+            moves: 0,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Points scored so far, including the bonus for clearing the board
+    /// and, once the game is over, the penalty for the stones left.
+    pub fn score(&self) -> i32 {
+        if self.is_terminal() {
+            self.score - self.penalty()
+        } else {
+            self.score
+        }
+    }
+
+    /// Deduction for the stones left at the end of the game.
+    fn penalty(&self) -> i32 {
+        let mut counts = [0; MAX_COLORS + 1];
+        for &cell in &self.cells {
+            counts[cell as usize] += 1;
+        }
+        counts[1..].iter()
+            .filter(|&&n| n > 0)
+            .map(|&n| (n - 2) * (n - 2))
+            .sum()
+    }
+
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    /// Color of the stone at `col` and `row`, if any.
+    pub fn stone(&self, col: usize, row: usize) -> Option<u8> {
+        match self.cells[self.index(col, row)] {
+            0 => None,
+            cell => Some(cell - 1),
+        }
+    }
+
+    /// Number of stones left on the board.
+    pub fn stones_left(&self) -> usize {
+        self.cells.iter().filter(|&&cell| cell != 0).count()
+    }
+
+    /// Number of different colors left on the board.
+    pub fn colors(&self) -> usize {
+        let mut present = [false; MAX_COLORS + 1];
+        for &cell in &self.cells {
+            present[cell as usize] = true;
+        }
+        present[1..].iter().filter(|&&p| p).count()
+    }
+
+    fn index(&self, col: usize, row: usize) -> usize {
+        col * self.height + row
+    }
+
+    fn neighbors(&self, i: usize) -> [Option<usize>; 4] {
+        let (col, row) = (i / self.height, i % self.height);
+        [
+            if col > 0 { Some(i - self.height) } else { None },
+            if col + 1 < self.width { Some(i + self.height) } else { None },
+            if row > 0 { Some(i - 1) } else { None },
+            if row + 1 < self.height { Some(i + 1) } else { None },
+        ]
+    }
+
+    /// Mark the stones connected to `start` with the same color in `seen`
+    /// and return them.
+    fn group(&self, start: usize, seen: &mut [bool]) -> Vec<usize> {
+        let color = self.cells[start];
+        let mut group = vec![start];
+        seen[start] = true;
+        let mut next = 0;
+        while next < group.len() {
+            for &neighbor in self.neighbors(group[next]).iter().flatten() {
+                if !seen[neighbor] && self.cells[neighbor] == color {
+                    seen[neighbor] = true;
+                    group.push(neighbor);
+                }
+            }
+            next += 1;
+        }
+        group
+    }
+
+    /// Let the stones fall down and close empty columns.
+    fn collapse(&mut self) {
+        let mut to = 0;
+        for col in 0..self.width {
+            let start = to;
+            for i in col * self.height..(col + 1) * self.height {
+                if self.cells[i] != 0 {
+                    self.cells[to] = self.cells[i];
+                    to += 1;
+                }
+            }
+            if to > start {
+                // Fill the rest of the column, which is not empty
+                let end = start + self.height;
+                for cell in &mut self.cells[to..end] {
+                    *cell = 0;
+                }
+                to = end;
+            }
+        }
+        for cell in &mut self.cells[to..] {
+            *cell = 0;
+        }
+    }
+}
+
+/// Read boards separated by blank lines, each in the format of `from_str`.
+pub fn read_positions(text: &str) -> Result<Vec<SameGame>, GameError> {
+    let mut boards = vec![String::new()];
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !boards.last().unwrap().is_empty() {
+                boards.push(String::new());
+            }
+        } else {
+            let board = boards.last_mut().unwrap();
+            board.push_str(line);
+            board.push('\n');
+        }
+    }
+    boards.iter()
+        .filter(|board| !board.is_empty())
+        .map(|board| board.parse())
+        .collect()
+}
+
+impl FromStr for SameGame {
+    type Err = GameError;
+
+    /// Parse rows of color digits, top row first, separated by new lines
+    /// or `/`; `.` marks an empty cell. Stones must have fallen down and
+    /// empty columns must be on the right.
+    fn from_str(s: &str) -> Result<SameGame, GameError> {
+        let rows = s.split(['\n', '/'])
+                .map(|row| row.chars().filter(|c| !c.is_whitespace()).collect::<Vec<char>>())
+                .filter(|row| !row.is_empty())
+                .collect::<Vec<_>>();
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 || width > 256 || height > 256 || rows.iter().any(|row| row.len() != width) {
+            return Err(GameError::Other(format!("rows of different or invalid length in {:?}", s)));
+        }
+
+        let mut game = SameGame {
+            width,
+            height,
+            cells: vec![0; width * height],
+            score: 0,
+            moves: 0,
+        };
+        for (r, row) in rows.iter().enumerate() {
+            for (col, &c) in row.iter().enumerate() {
+                let i = game.index(col, height - 1 - r);
+                game.cells[i] = match c {
+                    '.' => 0,
+                    _ => match c.to_digit(10) {
+                        Some(color) => color as u8 + 1,
+                        None => return Err(GameError::Other(format!("unexpected {:?} in {:?}", c, s))),
+                    },
+                };
+            }
+        }
+
+        let mut settled = game.clone();
+        settled.collapse();
+        if settled != game {
+            return Err(GameError::Other(format!("stones above empty cells in {:?}", s)));
+        }
+        Ok(game)
+    }
+}
+
+impl fmt::Display for SameGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in (0..self.height).rev() {
+            for col in 0..self.width {
+                match self.stone(col, row) {
+                    Some(color) => write!(f, "{}", color)?,
+                    None => write!(f, ".")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game<Action> for SameGame {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    /// One action per group, naming its stone that comes first column by
+    /// column from the bottom left.
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        let mut seen = vec![false; self.cells.len()];
+        for i in 0..self.cells.len() {
+            if self.cells[i] == 0 || seen[i] {
+                continue;
+            }
+            if self.group(i, &mut seen).len() >= 2 {
+                actions.push(Action { col: (i / self.height) as u8, row: (i % self.height) as u8 });
+            }
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        !(0..self.cells.len()).any(|i| {
+            let (col, row) = (i / self.height, i % self.height);
+            self.cells[i] != 0 && (
+                (col + 1 < self.width && self.cells[i + self.height] == self.cells[i]) ||
+                (row + 1 < self.height && self.cells[i + 1] == self.cells[i]))
+        })
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    /// Remove the group of the stone named by `action`, which may be any
+    /// stone of the group.
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        let (col, row) = (action.col as usize, action.row as usize);
+        if col >= self.width || row >= self.height || self.cells[self.index(col, row)] == 0 {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        let start = self.index(col, row);
+        let group = self.group(start, &mut vec![false; self.cells.len()]);
+        if group.len() < 2 {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+
+        for &i in &group {
+            self.cells[i] = 0;
+        }
+        self.collapse();
+        let removed = group.len() as i32 - 2;
+        self.score += removed * removed;
+        if self.cells[0] == 0 {
+            self.score += CLEAR_BONUS;
+        }
+        self.moves += 1;
+        Ok(())
+    }
+
+    fn reward(&self) -> f32 {
+        self.score() as f32
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    /// The position and color of the stone, so that NRPA can tell apart
+    /// groups of different colors at the same place.
+    fn action_code(&self, action: &Action) -> u64 {
+        let color = self.cells[self.index(action.col as usize, action.row as usize)];
+        (color as u64) << 16 | (action.col as u64) << 8 | action.row as u64
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use test::Bencher;
+
+    use mcts::*;
+    use nested::*;
+    use samegame::*;
+    use utils::seeded_rng;
+
+    fn position(s: &str) -> SameGame {
+        s.parse().unwrap()
+    }
+
+    fn at(col: u8, row: u8) -> Action {
+        Action { col, row }
+    }
+
+    /// Best score reachable from `game`.
+    fn solve(game: &SameGame, cache: &mut HashMap<Vec<u8>, i32>) -> i32 {
+        if let Some(&best) = cache.get(&game.cells) {
+            return game.score + best;
+        }
+        let best = game.allowed_actions().iter()
+            .map(|action| {
+                let mut next = game.clone();
+                next.make_move(action);
+                solve(&next, cache) - game.score
+            })
+            .max()
+            .unwrap_or_else(|| game.score() - game.score);
+        cache.insert(game.cells.clone(), best);
+        game.score + best
+    }
+
+    #[test]
+    fn test_from_str() {
+        let game = position("12.\n112\n221");
+        assert_eq!((game.width(), game.height()), (3, 3));
+        assert_eq!(game.stone(0, 2), Some(1));
+        assert_eq!(game.stone(2, 2), None);
+        assert_eq!(game.stone(2, 0), Some(1));
+        assert_eq!(game.stones_left(), 8);
+        assert_eq!(game.to_string(), "12.\n112\n221\n");
+        assert_eq!(position("1 2 . / 1 1 2 / 2 2 1"), game);
+
+        assert!("12\n1".parse::<SameGame>().is_err());
+        assert!("1a\n12".parse::<SameGame>().is_err());
+        assert!("".parse::<SameGame>().is_err());
+        // Floating stones and empty columns on the left
+        assert!("1.\n.1".parse::<SameGame>().is_err());
+        assert!(".1\n.1".parse::<SameGame>().is_err());
+    }
+
+    #[test]
+    fn test_random() {
+        let game = SameGame::new(0);
+        assert_eq!((game.width(), game.height(), game.stones_left()), (15, 15, 225));
+        assert!((0..15).all(|col| game.stone(col, 0).unwrap() < 5));
+        assert_eq!(SameGame::new(0), game);
+        assert!(SameGame::new(1) != game);
+        assert!(SameGame::random(0, 5, 3, 0).is_err());
+        assert!(SameGame::random(5, 5, 11, 0).is_err());
+    }
+
+    #[test]
+    fn test_read_positions() {
+        let set = (0..20).map(SameGame::new).collect::<Vec<_>>();
+        let text = set.iter().map(|game| game.to_string()).collect::<Vec<_>>().join("\n");
+        assert_eq!(read_positions(&text).unwrap(), set);
+        assert_eq!(read_positions("\n12\n21\n\n\n11\n22\n").unwrap(), vec![position("12/21"), position("11/22")]);
+        assert!(read_positions("12\n21\n\n1a\n").is_err());
+        assert_eq!(position("1.\n13").colors(), 2);
+    }
+
+    #[test]
+    fn test_actions() {
+        let game = position("12.\n112\n221");
+        // The groups of 1 on the left and of 2 at the bottom left
+        assert_eq!(game.allowed_actions(), vec![at(0, 0), at(0, 1)]);
+        assert!(!game.is_terminal());
+        assert!(game.clone().try_make_move(&at(2, 0)).is_err());
+        assert!(game.clone().try_make_move(&at(2, 2)).is_err());
+        assert!(game.clone().try_make_move(&at(3, 0)).is_err());
+
+        assert!(position("12\n21").is_terminal());
+        assert!(position("12\n21").allowed_actions().is_empty());
+        assert!(position("1.\n2.").is_terminal());
+    }
+
+    #[test]
+    fn test_removal() {
+        // Any stone of a group removes all of it
+        let mut game = position("12.\n112\n221");
+        game.make_move(&at(1, 1));
+        assert_eq!(game.to_string(), "...\n.22\n221\n");
+        assert_eq!(game.score(), 1);
+        assert_eq!(game.moves(), 1);
+
+        // Empty columns are closed
+        let mut game = position("1.2\n123\n123");
+        game.make_move(&at(0, 0));
+        assert_eq!(game.to_string(), ".2.\n23.\n23.\n");
+        assert_eq!(game.score(), 1);
+        game.make_move(&at(0, 0));
+        assert_eq!(game.to_string(), "2..\n3..\n3..\n");
+        assert_eq!(game.score(), 1);
+        game.make_move(&at(0, 0));
+        assert_eq!(game.to_string(), "...\n...\n2..\n");
+        assert!(game.is_terminal());
+        assert_eq!(game.decided_reward(), Some(0.));
+    }
+
+    #[test]
+    fn test_scoring() {
+        // Two stones score nothing, the bonus is for clearing the board
+        let mut game = position("11");
+        game.make_move(&at(0, 0));
+        assert_eq!(game.score(), 1000);
+        assert!(game.is_terminal());
+
+        let mut game = position("11111\n22222");
+        game.make_move(&at(0, 1));
+        assert_eq!(game.score(), 9);
+        game.make_move(&at(0, 0));
+        assert_eq!(game.score(), 9 + 9 + 1000);
+        assert_eq!(game.reward(), 1018.);
+
+        // Stones left at the end are scored as one group per color
+        assert_eq!(position("12\n21").score(), 0);
+        assert_eq!(position("1.\n2.").score(), -2);
+        assert_eq!(position("121\n212").score(), -2);
+        let mut game = position("111\n234");
+        game.make_move(&at(0, 1));
+        assert_eq!(game.to_string(), "...\n234\n");
+        assert_eq!(game.score(), 1 - 3);
+        assert_eq!(game.reward(), -2.);
+    }
+
+    #[test]
+    fn test_action_code() {
+        let game = position("12\n21\n12");
+        assert!(game.action_code(&at(0, 0)) != game.action_code(&at(0, 1)));
+        assert!(game.action_code(&at(0, 0)) != game.action_code(&at(1, 1)));
+    }
+
+    #[test]
+    fn test_search_finds_optimum() {
+        for seed in 0..3 {
+            let game = SameGame::random(5, 5, 3, seed).unwrap();
+            let best = solve(&game, &mut HashMap::new());
+            let found = nmcs(&game, 2, &mut seeded_rng(seed, 0)).unwrap();
+            assert_eq!(found.score, best as f32, "{}", game);
+
+            let mut replay = game.clone();
+            for action in &found.actions {
+                replay.make_move(action);
+            }
+            assert_eq!(replay.score(), best);
+        }
+    }
+
+    #[test]
+    fn test_mcts() {
+        let game = SameGame::new(0);
+        let mut mcts = MCTS::new(&game, 2);
+        mcts.search(50, 100.).unwrap();
+        let action = mcts.best_action().expect("should give some action");
+        game.clone().make_move(&action);
+    }
+
+    #[bench]
+    fn bench_playout(b: &mut Bencher) {
+        let game = SameGame::new(0);
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
+    }
+
+    #[bench]
+    fn bench_fill_actions(b: &mut Bencher) {
+        let game = SameGame::new(0);
+        let mut actions = Vec::new();
+        b.iter(|| {
+            game.fill_actions(&mut actions);
+            actions.len()
+        });
+    }
+}
@@ -0,0 +1,374 @@
+//!
+//! Implementation of Tic-tac-toe.
+//!
+//! Two players, `x` (player 0, moves first) and `o` (player 1), take turns
+//! marking the fields of a 3x3 board. Whoever first gets three marks in a
+//! row, column or diagonal wins and gains a reward of 1; the opponent gets
+//! -1. A full board without three in a row is a draw worth 0 to both.
+//!
+//! Positions can be given as strings such as `"xo./.x./..o"`: one character
+//! per field, row by row; `/`, `|` and whitespace are ignored.
+//!
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+
+const FIELDS: usize = 9;
+const FULL: u16 = 0x1ff;
+
+/// Fields of every row, column and diagonal, as bits numbered row by row.
+pub const LINES: [u16; 8] = [
+    0b000_000_111, 0b000_111_000, 0b111_000_000,
+    0b001_001_001, 0b010_010_010, 0b100_100_100,
+    0b100_010_001, 0b001_010_100,
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Action {
+    field: u8
+}
+impl GameAction for Action {}
+
+impl Action {
+    /// Mark the field in the given row and column (both 0..3).
+    pub fn at(row: usize, col: usize) -> Action {
+        Action { field: (3 * row + col) as u8 }
+    }
+
+    /// The field marked, numbered row by row from 0 to 8.
+    pub fn field(&self) -> usize {
+        self.field as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TicTacToe {
+    marks: [u16; 2],                    // fields marked by x and o
+}
+
+impl TicTacToe {
+    pub fn new() -> TicTacToe {
+        TicTacToe::default()
+    }
+
+    fn occupied(&self) -> u16 {
+        self.marks[0] | self.marks[1]
+    }
+
+    /// The player who completed a line, if any.
+    pub fn winner(&self) -> Option<usize> {
+        (0..2).find(|&player| {
+            LINES.iter().any(|&line| self.marks[player] & line == line)
+        })
+    }
+}
+
+impl FromStr for TicTacToe {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> Result<TicTacToe, GameError> {
+        let mut game = TicTacToe::new();
+        let mut field = 0;
+        for c in s.chars().filter(|c| !(c.is_whitespace() || *c == '/' || *c == '|')) {
+            if field >= FIELDS {
+                return Err(GameError::Other(format!("too many fields in {:?}", s)));
+            }
+            match c {
+                'x' | 'X' => game.marks[0] |= 1 << field,
+                'o' | 'O' => game.marks[1] |= 1 << field,
+                '.' | '-' => {},
+                _ => return Err(GameError::Other(format!("unexpected {:?} in {:?}", c, s))),
+            }
+            field += 1;
+        }
+
+        let (x, o) = (game.marks[0].count_ones(), game.marks[1].count_ones());
+        if field != FIELDS {
+            Err(GameError::Other(format!("expected {} fields in {:?}", FIELDS, s)))
+        } else if x != o && x != o + 1 {
+            Err(GameError::Other(format!("{} x and {} o cannot be reached by alternating moves", x, o)))
+        } else {
+            Ok(game)
+        }
+    }
+}
+
+impl fmt::Display for TicTacToe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..3 {
+            for col in 0..3 {
+                let bit = 1 << (3 * row + col);
+                let c = if self.marks[0] & bit != 0 {
+                    'x'
+                } else if self.marks[1] & bit != 0 {
+                    'o'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game<Action> for TicTacToe {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        if self.is_terminal() {
+            return;
+        }
+        let occupied = self.occupied();
+        actions.extend((0..FIELDS as u8)
+            .filter(|&field| occupied & (1 << field) == 0)
+            .map(|field| Action { field }));
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.occupied() == FULL || self.winner().is_some()
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        if self.is_terminal() {
+            return None;
+        }
+        let empty = !self.occupied() & FULL;
+        let mut k = rng.gen_range(0, empty.count_ones());
+        let mut field = 0;
+        loop {
+            if empty & (1 << field) != 0 {
+                if k == 0 {
+                    return Some(Action { field });
+                }
+                k -= 1;
+            }
+            field += 1;
+        }
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        if action.field() >= FIELDS || self.occupied() & (1 << action.field) != 0 || self.is_terminal() {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        let player = self.current_player();
+        self.marks[player] |= 1 << action.field;
+        Ok(())
+    }
+
+    /// Reward of `x`.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn supports_unmake(&self) -> bool {
+        true
+    }
+
+    fn unmake_move(&mut self, action: &Action) {
+        let bit = 1 << action.field;
+        for marks in &mut self.marks {
+            *marks &= !bit;
+        }
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.occupied().count_ones() as usize % 2
+    }
+
+    fn reward_for(&self, player: usize) -> f32 {
+        match self.winner() {
+            Some(winner) if winner == player => 1.,
+            Some(_) => -1.,
+            None => 0.,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use test::Bencher;
+
+    use agent::{Agent, Budget, MctsAgent, RandomAgent};
+    use arena::Arena;
+    use error::GameError;
+    use mcts::*;
+    use tictactoe::*;
+    use utils::seeded_rng;
+
+    fn position(s: &str) -> TicTacToe {
+        s.parse().unwrap()
+    }
+
+    /// Exact value of the game for the player to move.
+    fn negamax(game: &mut TicTacToe) -> f32 {
+        if game.is_terminal() {
+            return game.reward_for(game.current_player());
+        }
+        let mut best = -1.;
+        for action in game.allowed_actions() {
+            game.make_move(&action);
+            best = f32::max(best, -negamax(game));
+            game.unmake_move(&action);
+        }
+        best
+    }
+
+    /// A perfect player: the first action with the best exact value.
+    fn perfect_action(game: &TicTacToe) -> Action {
+        let mut game = *game;
+        let value = negamax(&mut game);
+        game.allowed_actions().into_iter()
+            .find(|action| {
+                game.make_move(action);
+                let keeps_value = -negamax(&mut game) == value;
+                game.unmake_move(action);
+                keeps_value
+            })
+            .unwrap()
+    }
+
+    fn mcts_action(game: &TicTacToe, seed: u32) -> Action {
+        let mut mcts = MCTS::with_seed(game, 1, seed);
+        mcts.search(2000, 1.).unwrap();
+        mcts.best_action().unwrap()
+    }
+
+    #[test]
+    fn test_from_str() {
+        let game = position("xo./.x./..o");
+        assert_eq!(format!("{}", game), "xo.\n.x.\n..o\n");
+        assert_eq!(game.current_player(), 0);
+        assert_eq!(position("x........").current_player(), 1);
+
+        assert!("xx.......".parse::<TicTacToe>().is_err());
+        assert!("xo.......x".parse::<TicTacToe>().is_err());
+        assert!("xo?......".parse::<TicTacToe>().is_err());
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut game = TicTacToe::new();
+        assert_eq!(game.allowed_actions().len(), 9);
+        game.make_move(&Action::at(1, 1));
+        assert_eq!(game.try_make_move(&Action::at(1, 1)), Err(GameError::IllegalMove("Action { field: 4 }".to_string())));
+
+        let won = position("xxx/oo./...");
+        assert_eq!(won.winner(), Some(0));
+        assert!(won.is_terminal());
+        assert!(won.allowed_actions().is_empty());
+        assert_eq!((won.reward_for(0), won.reward_for(1)), (1., -1.));
+        assert_eq!(won.decided_reward(), Some(1.));
+        assert!(won.clone().try_make_move(&Action::at(2, 2)).is_err());
+
+        let draw = position("xox/xoo/oxx");
+        assert_eq!(draw.winner(), None);
+        assert!(draw.is_terminal());
+        assert_eq!(draw.decided_reward(), Some(0.));
+    }
+
+    #[test]
+    fn test_unmake_move() {
+        let mut game = position("xx./oo./...");
+        let before = game;
+        for action in game.allowed_actions() {
+            game.make_move(&action);
+            game.unmake_move(&action);
+            assert_eq!(game, before);
+        }
+    }
+
+    #[test]
+    fn test_random_action() {
+        let game = position("xo./.x./..o");
+        let mut rng = seeded_rng(0, 0);
+        let allowed = game.allowed_actions();
+        for _ in 0..100 {
+            assert!(allowed.contains(&game.random_action(&mut rng).unwrap()));
+        }
+        assert_eq!(position("xxx/oo./...").random_action(&mut rng), None);
+    }
+
+    #[test]
+    fn test_forced_wins() {
+        // Win immediately rather than blocking
+        assert_eq!(mcts_action(&position("xx./oo./..."), 0), Action::at(0, 2));
+        assert_eq!(mcts_action(&position("xx./oo./x.."), 0), Action::at(1, 2));
+        // Block the only threat
+        assert_eq!(mcts_action(&position("oo./x../..x"), 0), Action::at(0, 2));
+        // Block with a fork that creates two threats at once
+        let game = position("x.o/.o./..x");
+        let action = mcts_action(&game, 0);
+        assert_eq!(action, Action::at(2, 0));
+        let mut next = game;
+        next.make_move(&action);
+        assert_eq!(-negamax(&mut next), 1.);
+    }
+
+    #[test]
+    fn test_never_loses() {
+        // Against a perfect player from both seats
+        for mcts_player in 0..2 {
+            let mut game = TicTacToe::new();
+            while !game.is_terminal() {
+                let action = if game.current_player() == mcts_player {
+                    mcts_action(&game, game.occupied() as u32)
+                } else {
+                    perfect_action(&game)
+                };
+                game.make_move(&action);
+            }
+            assert_eq!(game.winner(), None, "\n{}", game);
+        }
+
+        // Against random players
+        let arena = Arena::new(&TicTacToe::new());
+        let mut mcts = MctsAgent::new(1, Budget::Iterations(1000), 1.);
+        let mut random = RandomAgent::new();
+        let results = arena.run(&mut [&mut mcts, &mut random], 10).unwrap();
+        assert_eq!(results[0].losses, 0);
+        assert!(results[0].wins > 5);
+        assert!(Agent::<TicTacToe, Action>::name(&mcts).starts_with("mcts"));
+    }
+
+    #[bench]
+    fn bench_search(b: &mut Bencher) {
+        let game = TicTacToe::new();
+        b.iter(|| {
+            let mut mcts = MCTS::new(&game, 1);
+            mcts.search(100, 1.).unwrap();
+            mcts.best_action()
+        })
+    }
+}
@@ -0,0 +1,325 @@
+//!
+//! Tune the exploration constant and search settings by playing games.
+//!
+//! A `Tuner` plays complete games with an MCTS solver for a candidate
+//! configuration and scores it by the mean final reward. Every candidate
+//! is evaluated on the same sequence of game seeds (common random numbers),
+//! which makes the comparison between candidates much less noisy.
+//!
+//! [1] J. C. Spall: Implementation of the Simultaneous Perturbation Algorithm
+//!     for Stochastic Optimization (IEEE Trans. Aerospace and Electronic Systems, 1998)
+//!
+
+use std::cmp::{max, Ordering};
+use std::marker::PhantomData;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{Game, GameAction, MCTS, SearchSettings, final_reward};
+
+#[derive(Debug, Copy, Clone)]
+/// A configuration of the search to be tuned.
+pub struct Candidate {
+    /// Exploration constant passed to `MCTS::search`.
+    pub c: f32,
+    pub settings: SearchSettings,
+}
+
+impl Candidate {
+    /// A candidate with the given exploration constant and default settings.
+    pub fn with_c(c: f32) -> Candidate {
+        Candidate {
+            c,
+            settings: SearchSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Rewards a candidate achieved.
+pub struct Evaluation {
+    pub candidate: Candidate,
+    /// Final reward of every game played, in order of the seeds.
+    pub rewards: Vec<f32>,
+}
+
+impl Evaluation {
+    /// Number of games played.
+    pub fn games(&self) -> usize {
+        self.rewards.len()
+    }
+
+    /// Mean final reward.
+    pub fn mean(&self) -> f32 {
+        self.rewards.iter().sum::<f32>() / self.rewards.len() as f32
+    }
+
+    /// Standard error of the mean (0 for fewer than two games).
+    pub fn std_err(&self) -> f32 {
+        let n = self.rewards.len() as f32;
+        if n < 2. {
+            return 0.;
+        }
+        let mean = self.mean();
+        let var = self.rewards.iter()
+                .map(|r| (r - mean) * (r - mean))
+                .sum::<f32>() / (n - 1.);
+        (var / n).sqrt()
+    }
+
+    /// Normal approximation of the confidence interval of the mean,
+    /// e.g. `z = 1.96` for 95%.
+    pub fn confidence_interval(&self, z: f32) -> (f32, f32) {
+        let mean = self.mean();
+        let err = z * self.std_err();
+        (mean - err, mean + err)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Settings of the SPSA optimizer.
+///
+/// The step sizes follow Spall's recommendations: `a_k = a / (k + 1 + big_a)^0.602`
+/// and `c_k = c / (k + 1)^0.101`. `a` has to be chosen relative to the scale
+/// of the rewards.
+pub struct Spsa {
+    /// Number of gradient steps.
+    pub iterations: usize,
+    /// Games played per side of every gradient estimate.
+    pub games: usize,
+    pub a: f32,
+    pub big_a: f32,
+    /// Size of the perturbations.
+    pub c: f32,
+}
+
+impl Default for Spsa {
+    fn default() -> Spsa {
+        Spsa {
+            iterations: 100,
+            games: 2,
+            a: 0.1,
+            big_a: 10.,
+            c: 0.1,
+        }
+    }
+}
+
+/// Plays games with MCTS solvers to compare candidate configurations.
+#[derive(Debug, Clone)]
+pub struct Tuner<G: Game<A>, A: GameAction> {
+    game: G,
+    ensemble_size: usize,
+    iterations: usize,
+    max_moves: Option<usize>,
+    first_seed: u32,
+    _action: PhantomData<A>,
+}
+
+impl<G: Game<A>, A: GameAction> Tuner<G, A> {
+
+    /// Play from `game` with an ensemble of `ensemble_size` trees and
+    /// `iterations` MCTS iterations per move.
+    pub fn new(game: &G, ensemble_size: usize, iterations: usize) -> Tuner<G, A> {
+        Tuner {
+            game: game.clone(),
+            ensemble_size,
+            iterations,
+            max_moves: None,
+            first_seed: 0,
+            _action: PhantomData,
+        }
+    }
+
+    /// Stop every game after `max_moves` moves (`None`: play until the end).
+    pub fn set_max_moves(&mut self, max_moves: Option<usize>) {
+        self.max_moves = max_moves;
+    }
+
+    /// Use the game seeds starting at `seed`.
+    pub fn set_first_seed(&mut self, seed: u32) {
+        self.first_seed = seed;
+    }
+
+    /// Play a single game with the given candidate and return its final reward.
+    pub fn play(&self, candidate: &Candidate, seed: u32) -> Result<f32, GameError> {
+        let mut game = self.game.clone();
+        game.set_rng_seed(seed);
+        let mut mcts = MCTS::with_seed(&game, self.ensemble_size, seed);
+        mcts.set_settings(candidate.settings);
+
+        let mut moves = 0;
+        while self.max_moves.is_none_or(|max_moves| moves < max_moves) {
+            mcts.search(self.iterations, candidate.c)?;
+            match mcts.best_action() {
+                Some(action) => {
+                    game.try_make_move(&action)?;
+                    mcts.advance_game(&game);
+                    moves += 1;
+                },
+                None => break
+            }
+        }
+        Ok(final_reward(&game))
+    }
+
+    /// Play `games` games with the given candidate.
+    pub fn evaluate(&self, candidate: &Candidate, games: usize) -> Result<Evaluation, GameError> {
+        let mut evaluation = Evaluation {
+            candidate: *candidate,
+            rewards: Vec::new(),
+        };
+        self.play_more(&mut evaluation, games)?;
+        Ok(evaluation)
+    }
+
+    /// Play `games` more games for an evaluation, continuing its seeds.
+    fn play_more(&self, evaluation: &mut Evaluation, games: usize) -> Result<(), GameError> {
+        let played = evaluation.rewards.len();
+        for i in played..played + games {
+            let reward = self.play(&evaluation.candidate, self.first_seed + i as u32)?;
+            evaluation.rewards.push(reward);
+        }
+        Ok(())
+    }
+
+    /// Evaluate every candidate with `games` games; best first.
+    pub fn grid_search(&self, candidates: &[Candidate], games: usize) -> Result<Vec<Evaluation>, GameError> {
+        let mut evaluations = candidates.iter()
+                .map(|candidate| self.evaluate(candidate, games))
+                .collect::<Result<Vec<_>, _>>()?;
+        sort_by_mean(&mut evaluations);
+        Ok(evaluations)
+    }
+
+    /// Successive halving over the candidates.
+    ///
+    /// Every round the remaining candidates play `games` more games, then
+    /// the worse half is dropped. Spends most games on the promising
+    /// candidates. Returns all evaluations, the survivor first and the
+    /// others in reverse order of elimination.
+    pub fn successive_halving(&self, candidates: &[Candidate], games: usize) -> Result<Vec<Evaluation>, GameError> {
+        let mut remaining = candidates.iter()
+                .map(|candidate| Evaluation { candidate: *candidate, rewards: Vec::new() })
+                .collect::<Vec<_>>();
+        let mut eliminated = Vec::new();
+
+        loop {
+            for evaluation in &mut remaining {
+                self.play_more(evaluation, games)?;
+            }
+            sort_by_mean(&mut remaining);
+            let keep = remaining.len().div_ceil(2);
+            eliminated.extend(remaining.drain(keep..).rev());
+            if remaining.len() <= 1 {
+                break;
+            }
+        }
+        remaining.extend(eliminated.into_iter().rev());
+        Ok(remaining)
+    }
+
+    /// Optimize continuous parameters with SPSA.
+    ///
+    /// `to_candidate` maps a parameter vector to the candidate to play,
+    /// e.g. `|theta| Candidate::with_c(theta[0])`. Both perturbed
+    /// candidates of a gradient step play the same seeds. Returns the final
+    /// parameters together with an evaluation over `games` games.
+    pub fn spsa<F, R>(&self, initial: &[f32], to_candidate: F, spsa: &Spsa, games: usize, rng: &mut R) -> Result<(Vec<f32>, Evaluation), GameError>
+        where F: Fn(&[f32]) -> Candidate, R: Rng
+    {
+        let mut theta = initial.to_vec();
+        let mut tuner = self.clone();
+
+        for k in 0..spsa.iterations {
+            let a_k = spsa.a / (k as f32 + 1. + spsa.big_a).powf(0.602);
+            let c_k = spsa.c / (k as f32 + 1.).powf(0.101);
+            let delta = theta.iter()
+                    .map(|_| if rng.gen() { 1. } else { -1. })
+                    .collect::<Vec<f32>>();
+
+            let perturbed = |sign: f32| theta.iter().zip(&delta)
+                    .map(|(t, d)| t + sign * c_k * d)
+                    .collect::<Vec<_>>();
+            tuner.first_seed = self.first_seed + (k * spsa.games) as u32;
+            let plus = tuner.evaluate(&to_candidate(&perturbed(1.)), spsa.games)?.mean();
+            let minus = tuner.evaluate(&to_candidate(&perturbed(-1.)), spsa.games)?.mean();
+
+            for (t, d) in theta.iter_mut().zip(&delta) {
+                *t += a_k * (plus - minus) / (2. * c_k * d);
+            }
+        }
+
+        let evaluation = self.evaluate(&to_candidate(&theta), max(games, 1))?;
+        Ok((theta, evaluation))
+    }
+}
+
+/// Sort evaluations by mean reward, best first.
+fn sort_by_mean(evaluations: &mut [Evaluation]) {
+    evaluations.sort_by(|a, b| b.mean().partial_cmp(&a.mean()).unwrap_or(Ordering::Equal));
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use tuning::*;
+    use minigame::MiniGame;
+    use twofortyeight::TwoFortyEight;
+    use utils::seeded_rng;
+
+    #[test]
+    fn test_evaluation() {
+        let evaluation = Evaluation {
+            candidate: Candidate::with_c(1.),
+            rewards: vec![1., 3., 5., 7.],
+        };
+        assert_eq!(evaluation.games(), 4);
+        assert_eq!(evaluation.mean(), 4.);
+        assert!((evaluation.std_err() - (20f32 / 3. / 4.).sqrt()).abs() < 1e-5);
+        let (lo, hi) = evaluation.confidence_interval(1.96);
+        assert!(lo < 4. && hi > 4.);
+        assert!((hi + lo - 8.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_grid_search() {
+        let tuner = Tuner::new(&MiniGame::new(), 2, 50);
+        let candidates = [Candidate::with_c(0.5), Candidate::with_c(1.)];
+
+        let evaluations = tuner.grid_search(&candidates, 3).unwrap();
+        assert_eq!(evaluations.len(), 2);
+        assert!(evaluations.iter().all(|e| e.games() == 3));
+        assert!(evaluations[0].mean() >= evaluations[1].mean());
+
+        // The same seeds give the same rewards
+        assert_eq!(tuner.evaluate(&evaluations[0].candidate, 3).unwrap().rewards, evaluations[0].rewards);
+    }
+
+    #[test]
+    fn test_successive_halving() {
+        let mut tuner = Tuner::new(&TwoFortyEight::new(), 1, 5);
+        tuner.set_max_moves(Some(10));
+        let candidates = [0.1, 1., 10., 100., 1000.].iter()
+                .map(|&c| Candidate::with_c(c))
+                .collect::<Vec<_>>();
+
+        let evaluations = tuner.successive_halving(&candidates, 1).unwrap();
+        let games = evaluations.iter().map(|e| e.games()).collect::<Vec<_>>();
+        assert_eq!(games, vec![3, 3, 2, 1, 1]);
+    }
+
+    #[test]
+    fn test_spsa() {
+        let mut tuner = Tuner::new(&TwoFortyEight::new(), 1, 5);
+        tuner.set_max_moves(Some(10));
+        let spsa = Spsa { iterations: 3, games: 1, .. Spsa::default() };
+
+        let (theta, evaluation) = tuner.spsa(&[1.], |theta| Candidate::with_c(theta[0]), &spsa, 2, &mut seeded_rng(0, 0)).unwrap();
+        assert_eq!(theta.len(), 1);
+        assert_eq!(evaluation.candidate.c, theta[0]);
+        assert_eq!(evaluation.games(), 2);
+    }
+}
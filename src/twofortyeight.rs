@@ -2,6 +2,7 @@
 use std::fmt;
 use rand::{Rng, XorShiftRng, SeedableRng};
 
+use error::GameError;
 use mcts::{GameAction, Game};
 
 pub const WIDTH: usize = 4;
@@ -28,6 +29,8 @@ pub enum Action {
 }
 impl GameAction for Action {}
 
+const ACTIONS: [Action; 4] = [Action::Up, Action::Down, Action::Left, Action::Right];
+
 
 impl TwoFortyEight {
     /// Create a new empty game
@@ -49,73 +52,86 @@ impl TwoFortyEight {
         game
     }
 
-    /// Static method
-    fn merge_vec(vec: &Vec<u16>) -> (Vec<u16>, f32, bool) {
+    /// Static method: shift and merge the tiles of `line` towards its start.
+    ///
+    /// Works in place without allocating; returns the points gained and
+    /// whether the line changed.
+    fn merge_line(line: &mut [u16]) -> (f32, bool) {
         let mut points = 0.0;
+        let mut changed = false;
 
-        // first, remove zeros
-        let orig_len = vec.len();
-        let filtered_vec = vec.iter().map(|t| *t).filter(|&t| t > 0).collect::<Vec<u16>>();
-
-        // Remove duplicates
-        let mut merged = Vec::new();
+        // Tiles are read left to right and placed at `placed`, which never
+        // overtakes the read position.
+        let mut placed = 0;
         let mut next = 0;
-        for t in filtered_vec {
+        for idx in 0..line.len() {
+            let t = line[idx];
+            if t == 0 {
+                continue;
+            }
             if t == next {
-                merged.push(2*t);
+                changed |= line[placed] != 2*t;
+                line[placed] = 2*t;
+                placed += 1;
                 next = 0;
                 points += 2.* (t as f32);
             } else {
                 if next != 0 {
-                    merged.push(next);
+                    changed |= line[placed] != next;
+                    line[placed] = next;
+                    placed += 1;
                 }
                 next = t;
             }
         }
         if next != 0 {
-            merged.push(next);
+            changed |= line[placed] != next;
+            line[placed] = next;
+            placed += 1;
         }
 
-        // Make sure we keep the original length and notice any changes
-        let changed = orig_len != merged.len();
-        for _ in 0..(orig_len-merged.len()) {
-            merged.push(0);
+        // Keep the original length
+        for tile in &mut line[placed..] {
+            changed |= *tile != 0;
+            *tile = 0;
         }
-        (merged, points, changed)
+        (points, changed)
     }
 
-    /// Shift and merge in the given direction
-    fn shift_and_merge(board: [u16; WIDTH*HEIGHT], action: &Action) -> ([u16; WIDTH*HEIGHT], Option<f32>) {
-        let (start, ostride, istride) = match *action {
+    /// Board indices of the first line and the strides between and within
+    /// lines when shifting in the given direction.
+    fn strides(action: &Action) -> (isize, isize, isize) {
+        match *action {
             Action::Up    => ( 0,  1,  4),
             Action::Down  => (12,  1, -4),
             Action::Left  => ( 0,  4,  1),
             Action::Right => (15, -4, -1),
-        };
+        }
+    }
 
-        let start = start as isize;
-        let ostride = ostride as isize;
-        let istride = istride as isize;
+    /// Shift and merge in the given direction
+    fn shift_and_merge(board: [u16; WIDTH*HEIGHT], action: &Action) -> ([u16; WIDTH*HEIGHT], Option<f32>) {
+        let (start, ostride, istride) = TwoFortyEight::strides(action);
         assert!(HEIGHT == WIDTH);
 
         let mut new_board = [0; WIDTH*HEIGHT];
         let mut all_points = 0.0;    //  points we accumulate
-        let mut any_changed = false;  // did any of the vectors change?
+        let mut any_changed = false;  // did any of the lines change?
 
         for outer in 0..(HEIGHT as isize) {
-            let mut vec = Vec::with_capacity(HEIGHT);
-            for inner in 0..(HEIGHT as isize) {
-                let idx = start + outer*ostride + inner*istride;
-                vec.push(board[idx as usize]);
+            let mut line = [0; HEIGHT];
+            for (inner, tile) in line.iter_mut().enumerate() {
+                let idx = start + outer*ostride + (inner as isize)*istride;
+                *tile = board[idx as usize];
             }
 
-            let (merged_vec, points, changed) = TwoFortyEight::merge_vec(&vec);
+            let (points, changed) = TwoFortyEight::merge_line(&mut line);
             all_points += points;
             any_changed |= changed;
 
-            for inner in 0..(HEIGHT as isize) {
-                let idx = start + outer*ostride + inner*istride;
-                new_board[idx as usize] = merged_vec[inner as usize];
+            for (inner, tile) in line.iter().enumerate() {
+                let idx = start + outer*ostride + (inner as isize)*istride;
+                new_board[idx as usize] = *tile;
             }
         }
         if any_changed {
@@ -125,6 +141,26 @@ impl TwoFortyEight {
         }
     }
 
+    /// Would shifting in the given direction change the board?
+    ///
+    /// Much cheaper than `shift_and_merge`: a move is possible iff some
+    /// tile can slide into an empty field or merge with its neighbour.
+    fn can_move(board: &[u16; WIDTH*HEIGHT], action: &Action) -> bool {
+        let (start, ostride, istride) = TwoFortyEight::strides(action);
+
+        for outer in 0..(HEIGHT as isize) {
+            for inner in 0..(HEIGHT as isize - 1) {
+                let idx = start + outer*ostride + inner*istride;
+                let tile = board[idx as usize];
+                let behind = board[(idx + istride) as usize];
+                if behind != 0 && (tile == 0 || tile == behind) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     ///
     pub fn get_tile(&self, row: usize, col: usize) -> u16 {
         let idx = row * WIDTH + col;
@@ -150,8 +186,19 @@ impl TwoFortyEight {
     }
 
     /// Place a 2 into some random empty tile
+    ///
+    /// Panics if the board is full; see `try_random_spawn`.
     pub fn random_spawn(&mut self) {
-        assert!(!self.board_full());
+        self.try_random_spawn().expect("Board is full")
+    }
+
+    /// Place a 2 into some random empty tile
+    ///
+    /// Fails with `GameError::BoardFull` if there is no empty tile left.
+    pub fn try_random_spawn(&mut self) -> Result<(), GameError> {
+        if self.board_full() {
+            return Err(GameError::BoardFull);
+        }
 
         loop {
             let row = self.rng.gen::<usize>() % HEIGHT;
@@ -161,6 +208,7 @@ impl TwoFortyEight {
                 break;
             }
         }
+        Ok(())
 
         // This is much slower... even for nearly full borads.
         // And not correct, because it's not useing self.rng!
@@ -179,24 +227,51 @@ impl Game<Action> for TwoFortyEight {
 
     /// Return a list with all allowed actions given the current game state.
     fn allowed_actions(&self) -> Vec<Action> {
-        let actions = vec![Action::Up, Action::Down, Action::Left, Action::Right];
+        let mut actions = Vec::with_capacity(ACTIONS.len());
+        self.fill_actions(&mut actions);
+        actions
+    }
 
-        actions.iter().map(|t| *t).filter(|&a| {
-                let (_, points) = TwoFortyEight::shift_and_merge(self.board, &a);
-                match points {
-                    Some(_) => true,
-                    None => false
-                }
-            }).collect()
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        actions.extend(ACTIONS.iter().filter(|a| TwoFortyEight::can_move(&self.board, a)));
+    }
+
+    fn is_terminal(&self) -> bool {
+        !ACTIONS.iter().any(|a| TwoFortyEight::can_move(&self.board, a))
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        let mut allowed = [Action::Up; 4];
+        let mut n_allowed = 0;
+        for a in &ACTIONS {
+            if TwoFortyEight::can_move(&self.board, a) {
+                allowed[n_allowed] = *a;
+                n_allowed += 1;
+            }
+        }
+        if n_allowed == 0 {
+            None
+        } else {
+            Some(allowed[rng.gen::<usize>() % n_allowed])
+        }
     }
 
     /// Change the current game state according to the given action.
     fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move")
+    }
+
+    /// Change the current game state according to the given action.
+    ///
+    /// Moves that would not change the board are illegal.
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
         let (new_board, points) = TwoFortyEight::shift_and_merge(self.board, action);
-        self.score += points.expect("Illegal move");
+        let points = points.ok_or_else(|| GameError::IllegalMove(format!("{:?}", action)))?;
+        self.score += points;
         self.moves += 1;
         self.board = new_board;
-        self.random_spawn()
+        self.try_random_spawn()
     }
 
     /// Reward for the player when reaching the current game state.
@@ -255,6 +330,8 @@ mod tests {
 
     use mcts::*;
     use twofortyeight::*;
+    use utils::seeded_rng;
+    use error::GameError;
 
     #[test]
     fn test_new() {
@@ -302,6 +379,18 @@ mod tests {
             game.random_spawn();
         }
         assert!(game.board_full());
+        assert_eq!(game.try_random_spawn(), Err(GameError::BoardFull));
+    }
+
+    #[test]
+    fn test_try_make_move() {
+        let mut game = TwoFortyEight::new_empty();
+        game.set_tile(0, 0, 2);
+
+        assert_eq!(game.try_make_move(&Action::Up), Err(GameError::IllegalMove("Up".to_string())));
+        assert_eq!(game.moves, 0);
+        assert!(game.try_make_move(&Action::Down).is_ok());
+        assert_eq!(game.get_tile(3, 0), 2);
     }
 
     #[test]
@@ -323,7 +412,7 @@ mod tests {
             (vec![2, 2, 2, 2, 2]   , vec![4, 4, 2, 0, 0]),
             (vec![2, 0, 2, 0, 4]   , vec![4, 4, 0, 0, 0]),
             (vec![2, 2, 0, 4, 4]   , vec![4, 8, 0, 0, 0]),
-            (vec![2, 2, 4, 4, 4, 4], vec![4, 8, 8, 0, 0]),
+            (vec![2, 2, 4, 4, 4, 4], vec![4, 8, 8, 0, 0, 0]),
             (vec![4, 0, 0, 0, 0, 4], vec![8, 0, 0, 0, 0, 0]),
         ];
 
@@ -344,11 +433,36 @@ mod tests {
         );*/
 
         for (input, should) in test_cases {
-            let  output = TwoFortyEight::merge_vec(&input);
-            println!("merge_vec({:?}) => {:?}  (should be {:?})", input, output, should);
+            let mut output = input.clone();
+            let (points, changed) = TwoFortyEight::merge_line(&mut output);
+            println!("merge_line({:?}) => {:?}  (should be {:?})", input, output, should);
+            assert_eq!(output, should);
+            assert_eq!(changed, output != input);
+            assert!(points >= 0.);
         }
     }
 
+    #[test]
+    fn test_allowed_actions() {
+        let mut rng = seeded_rng(0, 0);
+        let mut game = TwoFortyEight::new();
+
+        while !game.is_terminal() {
+            // The cheap checks agree with actually shifting the board
+            let allowed = game.allowed_actions();
+            for a in &ACTIONS {
+                let (_, points) = TwoFortyEight::shift_and_merge(game.board, a);
+                assert_eq!(allowed.contains(a), points.is_some());
+            }
+
+            let action = game.random_action(&mut rng).unwrap();
+            assert!(allowed.contains(&action));
+            game.make_move(&action);
+        }
+        assert!(game.allowed_actions().is_empty());
+        assert_eq!(game.random_action(&mut rng), None);
+    }
+
     #[test]
     fn test_shift_and_merge() {
         let mut game = TwoFortyEight::new_empty();
@@ -367,16 +481,25 @@ mod tests {
     #[test]
     fn test_playout() {
         let game = TwoFortyEight::new();
-        let final_game = playout(&game);
+        let final_game = playout(&game, None, &mut seeded_rng(0, 0)).unwrap();
         println!("{}", final_game);
     }
 
+    #[test]
+    fn test_playout_max_depth() {
+        let game = TwoFortyEight::new();
+        let mut rng = seeded_rng(0, 0);
+        let final_game = playout(&game, Some(5), &mut rng).unwrap();
+        assert!(final_game.moves <= 5);
+        assert_eq!(playout_reward(&game, Some(0), &mut rng), Ok(game.evaluate()));
+    }
+
     #[test]
     fn test_mcts() {
         let game = TwoFortyEight::new();
         let mut mcts = MCTS::new(&game, 5);
 
-        mcts.search(25, 1.);
+        mcts.search(25, 1.).unwrap();
         let action = mcts.best_action();
         action.expect("should give some action");
     }
@@ -384,7 +507,8 @@ mod tests {
     #[bench]
     fn bench_playout(b: &mut Bencher) {
         let game = TwoFortyEight::new();
-        b.iter(|| playout(&game));
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
     }
 
     #[bench]
@@ -393,6 +517,23 @@ mod tests {
         b.iter(|| game.allowed_actions());
     }
 
+    #[bench]
+    fn bench_fill_actions(b: &mut Bencher) {
+        let game = TwoFortyEight::new();
+        let mut actions = Vec::new();
+        b.iter(|| {
+            game.fill_actions(&mut actions);
+            actions.len()
+        });
+    }
+
+    #[bench]
+    fn bench_random_action(b: &mut Bencher) {
+        let game = TwoFortyEight::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| game.random_action(&mut rng));
+    }
+
     #[bench]
     fn random_spawn_until_full(b: &mut Bencher) {
         b.iter(|| {
@@ -0,0 +1,424 @@
+//!
+//! Implementation of Ultimate tic-tac-toe.
+//!
+//! The board consists of nine tic-tac-toe boards arranged in a 3x3 meta
+//! board. Players `x` (player 0, moves first) and `o` (player 1) take turns
+//! marking a field of one of the small boards. Three marks in a row win a
+//! small board, and three small boards won in a row win the game with a
+//! reward of 1; the opponent gets -1.
+//!
+//! The field marked sends the opponent to the small board at the same
+//! position in the meta board: after a mark in the top right field of any
+//! board the opponent has to play in the top right board. Boards that are
+//! won or full are closed; a player sent to a closed board may instead play
+//! in any open board. The game is a draw worth 0 if all boards are closed
+//! without three won boards in a row.
+//!
+//! Boards and fields are both numbered row by row from 0 to 8.
+//!
+
+use std::fmt;
+
+use rand::Rng;
+
+use error::GameError;
+use mcts::{GameAction, Game};
+use tictactoe::LINES;
+
+const BOARDS: usize = 9;
+const FULL: u16 = 0x1ff;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Action {
+    board: u8,
+    field: u8,
+}
+impl GameAction for Action {}
+
+impl Action {
+    /// Mark `field` of the small board `board`, both numbered 0 to 8.
+    pub fn at(board: usize, field: usize) -> Action {
+        Action { board: board as u8, field: field as u8 }
+    }
+
+    pub fn board(&self) -> usize {
+        self.board as usize
+    }
+
+    pub fn field(&self) -> usize {
+        self.field as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct UltimateTicTacToe {
+    marks: [[u16; 2]; BOARDS],          // fields marked by x and o on every board
+    won: [u16; 2],                      // boards won by x and o
+    closed: u16,                        // boards won or full
+    target: Option<u8>,                 // board the player to move is sent to
+    moves: u8,
+}
+
+impl UltimateTicTacToe {
+    pub fn new() -> UltimateTicTacToe {
+        UltimateTicTacToe::default()
+    }
+
+    /// The board the player to move has to play in, or `None` if any open
+    /// board may be chosen.
+    pub fn target(&self) -> Option<usize> {
+        self.target.map(|board| board as usize)
+    }
+
+    /// The player who won `board`, if any.
+    pub fn board_winner(&self, board: usize) -> Option<usize> {
+        (0..2).find(|&player| self.won[player] & (1 << board) != 0)
+    }
+
+    /// Is `board` won or full?
+    pub fn is_closed(&self, board: usize) -> bool {
+        self.closed & (1 << board) != 0
+    }
+
+    /// The player who won three boards in a row, if any.
+    pub fn winner(&self) -> Option<usize> {
+        (0..2).find(|&player| {
+            LINES.iter().any(|&line| self.won[player] & line == line)
+        })
+    }
+
+    /// The boards the player to move may play in.
+    fn open_boards(&self) -> u16 {
+        match self.target {
+            Some(board) => 1 << board,
+            None => !self.closed & FULL,
+        }
+    }
+
+    fn empty_fields(&self, board: usize) -> u16 {
+        !(self.marks[board][0] | self.marks[board][1]) & FULL
+    }
+
+    /// Close `board` if the last mark won or filled it.
+    fn update_board(&mut self, board: usize) {
+        for player in 0..2 {
+            if LINES.iter().any(|&line| self.marks[board][player] & line == line) {
+                self.won[player] |= 1 << board;
+            }
+        }
+        if self.board_winner(board).is_some() || self.empty_fields(board) == 0 {
+            self.closed |= 1 << board;
+        }
+    }
+}
+
+impl fmt::Display for UltimateTicTacToe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..9 {
+            if row > 0 && row % 3 == 0 {
+                writeln!(f, "---+---+---")?;
+            }
+            for col in 0..9 {
+                if col > 0 && col % 3 == 0 {
+                    write!(f, "|")?;
+                }
+                let board = row / 3 * 3 + col / 3;
+                let bit = 1 << (row % 3 * 3 + col % 3);
+                let c = if self.marks[board][0] & bit != 0 {
+                    'x'
+                } else if self.marks[board][1] & bit != 0 {
+                    'o'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Game<Action> for UltimateTicTacToe {
+    fn allowed_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.fill_actions(&mut actions);
+        actions
+    }
+
+    fn fill_actions(&self, actions: &mut Vec<Action>) {
+        actions.clear();
+        if self.is_terminal() {
+            return;
+        }
+        let open = self.open_boards();
+        for board in (0..BOARDS).filter(|&board| open & (1 << board) != 0) {
+            let empty = self.empty_fields(board);
+            actions.extend((0..9)
+                .filter(|&field| empty & (1 << field) != 0)
+                .map(|field| Action::at(board, field)));
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.closed == FULL || self.winner().is_some()
+    }
+
+    fn random_action<R: Rng>(&self, rng: &mut R) -> Option<Action> {
+        if self.is_terminal() {
+            return None;
+        }
+        let open = self.open_boards();
+        let count = (0..BOARDS)
+            .filter(|&board| open & (1 << board) != 0)
+            .map(|board| self.empty_fields(board).count_ones())
+            .sum::<u32>();
+        let mut k = rng.gen_range(0, count);
+        for board in (0..BOARDS).filter(|&board| open & (1 << board) != 0) {
+            let empty = self.empty_fields(board);
+            if k >= empty.count_ones() {
+                k -= empty.count_ones();
+                continue;
+            }
+            for field in 0..9 {
+                if empty & (1 << field) != 0 {
+                    if k == 0 {
+                        return Some(Action::at(board, field));
+                    }
+                    k -= 1;
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    fn make_move(&mut self, action: &Action) {
+        self.try_make_move(action).expect("Illegal move");
+    }
+
+    fn try_make_move(&mut self, action: &Action) -> Result<(), GameError> {
+        let (board, field) = (action.board(), action.field());
+        if board >= BOARDS || field >= 9 || self.is_terminal() ||
+                self.open_boards() & (1 << board) == 0 ||
+                self.empty_fields(board) & (1 << field) == 0 {
+            return Err(GameError::IllegalMove(format!("{:?}", action)));
+        }
+        let player = self.current_player();
+        self.marks[board][player] |= 1 << field;
+        self.update_board(board);
+        self.target = if self.is_closed(field) { None } else { Some(field as u8) };
+        self.moves += 1;
+        Ok(())
+    }
+
+    /// Reward of `x`.
+    fn reward(&self) -> f32 {
+        self.reward_for(0)
+    }
+
+    fn set_rng_seed(&mut self, _: u32) { }
+
+    fn decided_reward(&self) -> Option<f32> {
+        if self.is_terminal() {
+            Some(self.reward())
+        } else {
+            None
+        }
+    }
+
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    fn current_player(&self) -> usize {
+        self.moves as usize % 2
+    }
+
+    fn reward_for(&self, player: usize) -> f32 {
+        match self.winner() {
+            Some(winner) if winner == player => 1.,
+            Some(_) => -1.,
+            None => 0.,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use test::Bencher;
+
+    use agent::{Budget, MctsAgent, RandomAgent};
+    use arena::Arena;
+    use mcts::*;
+    use ultimate_tictactoe::*;
+    use utils::seeded_rng;
+
+    /// Set the marks of `board` directly, e.g. to close it.
+    fn set_board(game: &mut UltimateTicTacToe, board: usize, x: u16, o: u16) {
+        game.marks[board] = [x, o];
+        game.update_board(board);
+    }
+
+    fn boards(actions: &[Action]) -> Vec<usize> {
+        let mut boards = actions.iter().map(|action| action.board()).collect::<Vec<_>>();
+        boards.dedup();
+        boards
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut game = UltimateTicTacToe::new();
+        assert_eq!(game.allowed_actions().len(), 81);
+        assert_eq!(game.target(), None);
+
+        // The field sends the opponent to the board at the same position
+        game.make_move(&Action::at(4, 2));
+        assert_eq!(game.target(), Some(2));
+        assert_eq!(game.current_player(), 1);
+        assert_eq!(game.allowed_actions(), (0..9).map(|field| Action::at(2, field)).collect::<Vec<_>>());
+        assert!(game.clone().try_make_move(&Action::at(4, 0)).is_err());
+        assert!(game.clone().try_make_move(&Action::at(9, 0)).is_err());
+
+        // Sent back to the same board, where a field is taken
+        game.make_move(&Action::at(2, 4));
+        assert_eq!(game.target(), Some(4));
+        assert_eq!(game.allowed_actions().len(), 8);
+        assert!(game.clone().try_make_move(&Action::at(4, 2)).is_err());
+        game.make_move(&Action::at(4, 4));
+        assert_eq!(game.target(), Some(4));
+        assert_eq!(game.allowed_actions().len(), 7);
+    }
+
+    #[test]
+    fn test_sent_to_won_board() {
+        let mut game = UltimateTicTacToe::new();
+        set_board(&mut game, 0, 0b000_000_111, 0b000_011_000);
+        assert_eq!(game.board_winner(0), Some(0));
+        assert!(game.is_closed(0));
+
+        // o is sent to the won board 0 and may choose any open board
+        game.make_move(&Action::at(4, 0));
+        assert_eq!(game.target(), None);
+        let actions = game.allowed_actions();
+        assert_eq!(boards(&actions), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(actions.len(), 8 * 9 - 1);
+        // The won board stays closed, even with empty fields left
+        assert!(game.clone().try_make_move(&Action::at(0, 6)).is_err());
+        assert!(game.clone().try_make_move(&Action::at(4, 0)).is_err());
+        assert!(game.clone().try_make_move(&Action::at(4, 1)).is_ok());
+
+        // Winning a board with its center field sends the opponent to it
+        let mut game = UltimateTicTacToe::new();
+        set_board(&mut game, 4, 0b100_000_001, 0b000_000_110);
+        game.make_move(&Action::at(4, 4));
+        assert_eq!(game.board_winner(4), Some(0));
+        assert_eq!(game.target(), None);
+        assert_eq!(boards(&game.allowed_actions()), vec![0, 1, 2, 3, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_sent_to_full_board() {
+        // Board 3 is full without a winner
+        let mut game = UltimateTicTacToe::new();
+        set_board(&mut game, 3, 0b011_100_101, 0b100_011_010);
+        assert!(game.is_closed(3));
+        assert_eq!(game.board_winner(3), None);
+
+        game.make_move(&Action::at(4, 3));
+        assert_eq!(game.target(), None);
+        let actions = game.allowed_actions();
+        assert_eq!(boards(&actions), vec![0, 1, 2, 4, 5, 6, 7, 8]);
+        assert_eq!(actions.len(), 8 * 9 - 1);
+
+        // Filling the board one is sent to closes it as well
+        let mut game = UltimateTicTacToe::new();
+        set_board(&mut game, 6, 0b011_100_101, 0b100_011_000);
+        game.make_move(&Action::at(8, 6));
+        assert_eq!(game.target(), Some(6));
+        assert_eq!(game.allowed_actions(), vec![Action::at(6, 1)]);
+        game.make_move(&Action::at(6, 1));
+        assert!(game.is_closed(6));
+        // Sent to board 1, which is open
+        assert_eq!(game.target(), Some(1));
+
+        // A move that closes the board it sends to gives a free choice
+        let mut game = UltimateTicTacToe::new();
+        set_board(&mut game, 5, 0b000_000_011, 0b000_011_000);
+        game.make_move(&Action::at(7, 5));
+        game.make_move(&Action::at(5, 5));
+        assert_eq!(game.board_winner(5), Some(1));
+        assert_eq!(game.target(), None);
+    }
+
+    #[test]
+    fn test_winner() {
+        let mut game = UltimateTicTacToe::new();
+        set_board(&mut game, 0, 0b000_000_111, 0b000_011_000);
+        set_board(&mut game, 4, 0b100_010_001, 0b000_000_110);
+        assert_eq!(game.winner(), None);
+        assert!(!game.is_terminal());
+        set_board(&mut game, 8, 0b111_000_000, 0b000_110_000);
+        assert_eq!(game.winner(), Some(0));
+        assert!(game.is_terminal());
+        assert!(game.allowed_actions().is_empty());
+        assert_eq!(game.random_action(&mut seeded_rng(0, 0)), None);
+        assert_eq!((game.reward_for(0), game.reward_for(1)), (1., -1.));
+        assert_eq!(game.decided_reward(), Some(1.));
+
+        // All boards closed without three in a row
+        let mut game = UltimateTicTacToe::new();
+        for (board, &winner) in [0, 1, 0, 0, 1, 1, 1, 0, 0].iter().enumerate() {
+            let line = LINES[board % 3];
+            let (x, o) = if winner == 0 { (line, 0) } else { (0, line) };
+            set_board(&mut game, board, x, o);
+        }
+        assert_eq!(game.winner(), None);
+        assert!(game.is_terminal());
+        assert_eq!(game.decided_reward(), Some(0.));
+    }
+
+    #[test]
+    fn test_random_action() {
+        let mut rng = seeded_rng(0, 0);
+        for seed in 0..20 {
+            let mut game = UltimateTicTacToe::new();
+            let mut moves = seeded_rng(seed, 1);
+            while let Some(action) = game.random_action(&mut moves) {
+                let allowed = game.allowed_actions();
+                assert!(allowed.contains(&action));
+                assert!(allowed.contains(&game.random_action(&mut rng).unwrap()));
+                game.make_move(&action);
+            }
+            assert!(game.is_terminal());
+            assert!(game.moves <= 81);
+        }
+    }
+
+    #[test]
+    fn test_against_random() {
+        let arena = Arena::new(&UltimateTicTacToe::new());
+        let mut mcts = MctsAgent::new(1, Budget::Iterations(300), 1.);
+        let mut random = RandomAgent::new();
+        let results = arena.run(&mut [&mut mcts, &mut random], 4).unwrap();
+        assert_eq!(results[0].wins, 4);
+    }
+
+    #[bench]
+    fn bench_playout(b: &mut Bencher) {
+        let game = UltimateTicTacToe::new();
+        let mut rng = seeded_rng(0, 0);
+        b.iter(|| playout(&game, None, &mut rng).unwrap());
+    }
+
+    #[bench]
+    fn bench_fill_actions(b: &mut Bencher) {
+        let game = UltimateTicTacToe::new();
+        let mut actions = Vec::new();
+        b.iter(|| {
+            game.fill_actions(&mut actions);
+            actions.len()
+        });
+    }
+}
@@ -1,29 +1,34 @@
 
 extern crate rand;
 
-use rand::Rng;
+use rand::{Rng, XorShiftRng, SeedableRng};
 
 
 /// Various small utility functions
 
-#[allow(dead_code)]
-/// Return a random element from the vector.
-pub fn choose_random<T>(vec: &Vec<T>) -> &T {
-    let mut rng = rand::thread_rng();
+/// Create a random number generator for the given seed and stream.
+///
+/// Different streams for the same seed yield independent sequences,
+/// e.g. one per tree of an ensemble or one per thread.
+pub fn seeded_rng(seed: u32, stream: u32) -> XorShiftRng {
+    // XorShift must not be seeded with all zeros
+    XorShiftRng::from_seed([seed, stream, 0x9e37_79b9, 0x7f4a_7c15 ^ seed.rotate_left(16)])
+}
 
+#[allow(dead_code)]
+/// Return a random element from the slice.
+pub fn choose_random<'a, R: Rng, T>(rng: &mut R, vec: &'a [T]) -> &'a T {
     let length = vec.len();
-    let idx = rng.gen::<usize>() % length as usize;
+    let idx = rng.gen::<usize>() % length;
 
     &vec[idx]
 }
 
 #[allow(dead_code)]
-/// Return a random mutable element from the vector.
-pub fn choose_random_mut<T>(vec: &mut Vec<T>) -> &mut T {
-    let mut rng = rand::thread_rng();
-
+/// Return a random mutable element from the slice.
+pub fn choose_random_mut<'a, R: Rng, T>(rng: &mut R, vec: &'a mut [T]) -> &'a mut T {
     let length = vec.len();
-    let idx = rng.gen::<usize>() % length as usize;
+    let idx = rng.gen::<usize>() % length;
 
     &mut vec[idx]
 }
@@ -38,14 +43,29 @@ mod tests {
 
     #[test]
     fn test_choose_random() {
+        let mut rng = seeded_rng(0, 0);
         let vec = vec![23];
 
-        assert_eq!(*choose_random(&vec), 23);
+        assert_eq!(*choose_random(&mut rng, &vec), 23);
+    }
+
+    #[test]
+    fn test_seeded_rng() {
+        let vec = (0..100).collect::<Vec<_>>();
+        let draw = |seed, stream| {
+            let mut rng = seeded_rng(seed, stream);
+            (0..10).map(|_| *choose_random(&mut rng, &vec)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(draw(1, 0), draw(1, 0));
+        assert!(draw(1, 0) != draw(1, 1));
+        assert!(draw(1, 0) != draw(2, 0));
     }
 
     #[bench]
     fn bench_choose_random10(b: &mut Bencher) {
+        let mut rng = seeded_rng(0, 0);
         let vec = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-        b.iter(|| choose_random(&vec))
+        b.iter(|| *choose_random(&mut rng, &vec))
     }
 }